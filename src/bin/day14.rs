@@ -1,18 +1,50 @@
 use std::cmp::{max, min};
 
-use advent_2022::{read_aoc_lines, Coord, Grid, InputIterator, OptionUtils};
+use advent_2022::{
+    check, read_aoc_lines, show_bool_grid, Coord, Grid, InputError, InputIterator, IntervalSet,
+    OptionUtils,
+};
 use anyhow::Result;
 
-fn parse_line(s: &str) -> Result<Vec<Coord>> {
-    s.split(" -> ")
-        .map(|p| {
-            let ps = p.split_once(',').ok_or_err()?;
-            Ok(Coord {
-                i: ps.1.parse()?,
-                j: ps.0.parse()?,
-            })
+fn parse_line(s: &str, line_number: usize) -> Result<Vec<Coord>> {
+    let error = |msg: String| InputError::new(format!("line {}: {}", line_number, msg));
+    if s.trim().is_empty() {
+        return Err(error("empty rock path".to_string()).into());
+    }
+    let path = s
+        .split("->")
+        .enumerate()
+        .map(|(n, p)| {
+            let p = p.trim();
+            if p.is_empty() {
+                return Err(error(format!("missing point {} of the path", n + 1)).into());
+            }
+            let (x, y) = p
+                .split_once(',')
+                .ok_or_else(|| error(format!("expected `x,y` but found `{}`", p)))?;
+            let parse = |v: &str| {
+                v.parse::<i64>()
+                    .map_err(|_| error(format!("invalid coordinate `{}` in `{}`", v, p)))
+            };
+            let c = Coord {
+                i: parse(y)?,
+                j: parse(x)?,
+            };
+            check(c.i >= 0, || {
+                error(format!("rock at {},{} is above the source", c.j, c.i))
+            })?;
+            Ok(c)
         })
-        .collect()
+        .collect::<Result<Vec<_>>>()?;
+    for w in path.windows(2) {
+        check(w[0].i == w[1].i || w[0].j == w[1].j, || {
+            error(format!(
+                "diagonal segment from {},{} to {},{}",
+                w[0].j, w[0].i, w[1].j, w[1].i
+            ))
+        })?;
+    }
+    Ok(path)
 }
 
 fn parse<I: InputIterator>(input: I) -> Result<(i64, i64, i64, Vec<Vec<Coord>>)> {
@@ -21,8 +53,9 @@ fn parse<I: InputIterator>(input: I) -> Result<(i64, i64, i64, Vec<Vec<Coord>>)>
     let mut max_x = 500;
 
     let lines = input
-        .map(|line| {
-            let ps = parse_line(line.as_ref())?;
+        .enumerate()
+        .map(|(n, line)| {
+            let ps = parse_line(line.as_ref(), n + 1)?;
             max_depth = max(max_depth, ps.iter().map(|x| x.i).max().ok_or_err()?);
             min_x = min(min_x, ps.iter().map(|x| x.j).min().ok_or_err()?);
             max_x = max(max_x, ps.iter().map(|x| x.j).max().ok_or_err()?);
@@ -30,7 +63,7 @@ fn parse<I: InputIterator>(input: I) -> Result<(i64, i64, i64, Vec<Vec<Coord>>)>
         })
         .collect::<Result<Vec<_>>>()?;
     Ok((max_depth, min_x, max_x, lines))
-} 
+}
 
 fn normalize(i: i64) -> i64 {
     if i == 0 {
@@ -40,58 +73,141 @@ fn normalize(i: i64) -> i64 {
     }
 }
 
-fn populate_grid(lines: Vec<Vec<Coord>>, grid: &mut Grid<bool>, offset: i64) { 
+// Every cell covered by the rock paths.
+fn rock_cells(lines: &[Vec<Coord>]) -> Vec<Coord> {
+    let mut cells = vec![];
     for line in lines {
-        for i in 0..(line.len() - 1) {
-            let start = line[i];
-            let vector = line[i + 1] - line[i];
+        // A path of a single point is a single rock.
+        cells.extend(line.first());
+        for segment in line.windows(2) {
+            let start = segment[0];
+            let vector = segment[1] - segment[0];
             let norm = Coord {
                 i: normalize(vector.i),
                 j: normalize(vector.j),
             };
             let magnitude = max(vector.i.abs(), vector.j.abs());
-            for m in 0..=magnitude {
-                let p = start
-                    + Coord {
-                        i: norm.i * m,
-                        j: norm.j * m - offset,
-                    };
-                grid[p] = true;
+            for m in 1..=magnitude {
+                cells.push(
+                    start
+                        + Coord {
+                            i: norm.i * m,
+                            j: norm.j * m,
+                        },
+                );
             }
         }
     }
+    cells
 }
 
-fn run_sim(grid: &mut Grid<bool>, start: Coord) -> i64 {
-    let moves = [
-        Coord { i: 1, j: 0 },
-        Coord { i: 1, j: -1 },
-        Coord { i: 1, j: 1 },
-    ];
-    let mut num_grains = 0;
-    loop {
-        let mut sand_coord = start;
-        'grain_movement: loop {
-            for m in moves {
-                let new_coord = sand_coord + m;
+fn populate_grid(lines: Vec<Vec<Coord>>, grid: &mut Grid<bool>, offset: i64) {
+    for c in rock_cells(&lines) {
+        grid[c - Coord { i: 0, j: offset }] = true;
+    }
+}
+
+// Draws the parsed rock paths, with the source of the sand as `+`.
+#[allow(dead_code)]
+fn render_cave<I: InputIterator>(input: I) -> Result<Vec<String>> {
+    let (max_depth, min_x, max_x, lines) = parse(input)?;
+    let mut grid = Grid::new(max_depth + 1, max_x - min_x + 1, false);
+    populate_grid(lines, &mut grid, min_x);
+    let mut rows = show_bool_grid(&grid);
+    let source = (500 - min_x) as usize;
+    rows[0].replace_range(source..=source, "+");
+    Ok(rows)
+}
+
+// The grains of sand fall down, then diagonally left, then diagonally right.
+const FALLING_SAND: [Coord; 3] = [
+    Coord { i: 1, j: 0 },
+    Coord { i: 1, j: -1 },
+    Coord { i: 1, j: 1 },
+];
+
+// Drops grains one at a time from each source in turn. Each grain takes the first move
+// that leads to an empty cell until none does, and comes to rest there. A source is done
+// once a grain from it leaves the grid or it is itself covered in sand.
+struct SandEngine {
+    moves: Vec<Coord>,
+    sources: Vec<Coord>,
+}
 
+#[allow(dead_code)]
+struct Grain {
+    source: usize,
+    rest: Option<Coord>,
+}
+
+#[derive(Debug, PartialEq)]
+struct SandStats {
+    grains: i64,
+    per_source: Vec<i64>,
+    per_column: Vec<i64>,
+}
+
+impl SandEngine {
+    fn new(sources: Vec<Coord>) -> SandEngine {
+        SandEngine {
+            moves: FALLING_SAND.to_vec(),
+            sources,
+        }
+    }
+
+    // Where a grain from `source` comes to rest, or None if it falls off the grid.
+    fn drop_grain(&self, grid: &Grid<bool>, source: Coord) -> Option<Coord> {
+        let mut sand_coord = source;
+        'grain_movement: loop {
+            for m in self.moves.iter() {
+                let new_coord = sand_coord + *m;
                 match grid.get(new_coord) {
-                    Some(occupied) => {
-                        if !occupied {
-                            sand_coord = new_coord;
-                            continue 'grain_movement;
-                        }
+                    Some(false) => {
+                        sand_coord = new_coord;
+                        continue 'grain_movement;
                     }
-                    None => return num_grains,
+                    Some(true) => {}
+                    None => return None,
                 }
             }
-            num_grains += 1;
-            grid[sand_coord] = true;
-            if sand_coord == start {
-                return num_grains
+            return Some(sand_coord);
+        }
+    }
+
+    fn run<F: FnMut(&Grain, &Grid<bool>)>(
+        &self,
+        mut grid: Grid<bool>,
+        mut on_grain: F,
+    ) -> (Grid<bool>, SandStats) {
+        let mut stats = SandStats {
+            grains: 0,
+            per_source: vec![0; self.sources.len()],
+            per_column: vec![0; grid.width() as usize],
+        };
+        let mut active: Vec<bool> = self.sources.iter().map(|_| true).collect();
+        while active.iter().any(|a| *a) {
+            for (source, start) in self.sources.iter().enumerate() {
+                if !active[source] {
+                    continue;
+                }
+                if grid.get(*start) != Some(&false) {
+                    active[source] = false;
+                    continue;
+                }
+                let rest = self.drop_grain(&grid, *start);
+                match rest {
+                    None => active[source] = false,
+                    Some(c) => {
+                        grid[c] = true;
+                        stats.grains += 1;
+                        stats.per_source[source] += 1;
+                        stats.per_column[c.j as usize] += 1;
+                    }
+                }
+                on_grain(&Grain { source, rest }, &grid);
             }
-            break 'grain_movement;
         }
+        (grid, stats)
     }
 }
 
@@ -102,12 +218,52 @@ fn part1<I: InputIterator>(input: I) -> Result<i64> {
 
     populate_grid(lines, &mut grid, min_x);
 
-    let num_grains = run_sim(&mut grid, Coord{i: 0, j:500 - min_x});
+    let engine = SandEngine::new(vec![Coord {
+        i: 0,
+        j: 500 - min_x,
+    }]);
+    let (_, stats) = engine.run(grid, |_, _| {});
 
-    Ok(num_grains)
+    Ok(stats.grains)
+}
+
+// Sand ends up in every cell above the floor that it can reach, so rather than dropping
+// grains this spreads the sand one row at a time: a cell fills if it isn't rock and one of
+// the three cells above it is filled. Only the filled intervals of each row are kept, so the
+// cost doesn't depend on the width of the triangle.
+fn fill_rows(lines: &[Vec<Coord>], source: Coord, floor_depth: i64) -> i64 {
+    let rows = (floor_depth - source.i).max(0) as usize;
+    let mut rocks = vec![vec![]; rows];
+    for c in rock_cells(lines) {
+        if (source.i..floor_depth).contains(&c.i) {
+            rocks[(c.i - source.i) as usize].push((c.j, c.j));
+        }
+    }
+
+    let mut filled: IntervalSet = [(source.j, source.j)].into_iter().collect();
+    let mut total = 0;
+    for (row, rock) in rocks.into_iter().enumerate() {
+        if row > 0 {
+            filled = filled
+                .intervals()
+                .iter()
+                .map(|(s, e)| (s - 1, e + 1))
+                .collect();
+        }
+        filled = filled.difference(&rock.into_iter().collect());
+        total += filled.total_length();
+    }
+    total
 }
 
 fn part2<I: InputIterator>(input: I) -> Result<i64> {
+    let (max_depth, _, _, lines) = parse(input)?;
+    Ok(fill_rows(&lines, Coord { i: 0, j: 500 }, max_depth + 2))
+}
+
+// Simulates part 2 grain by grain, which needs a grid as wide as the pile of sand.
+#[allow(dead_code)]
+fn simulate_part2<I: InputIterator>(input: I) -> Result<i64> {
     let (max_depth, min_x, max_x, lines) = parse(input)?;
     let floor_depth = max_depth + 2;
 
@@ -122,15 +278,24 @@ fn part2<I: InputIterator>(input: I) -> Result<i64> {
         grid[(floor_depth, x)] = true;
     }
 
-    let num_grains = run_sim(&mut grid, Coord{i: 0, j:500 - needed_min});
+    let engine = SandEngine::new(vec![Coord {
+        i: 0,
+        j: 500 - needed_min,
+    }]);
+    let (_, stats) = engine.run(grid, |_, _| {});
 
-    Ok(num_grains)
+    Ok(stats.grains)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::part1;
     use crate::part2;
+    use crate::render_cave;
+    use crate::simulate_part2;
+    use crate::SandEngine;
+    use crate::SandStats;
+    use advent_2022::{show_bool_grid, Coord, Grid};
     #[test]
     fn day14_test() {
         let input = vec![
@@ -139,10 +304,183 @@ mod tests {
         ];
         assert_eq!(part1(input.iter()).unwrap(), 24);
         assert_eq!(part2(input.iter()).unwrap(), 93);
+        assert_eq!(simulate_part2(input.iter()).unwrap(), 93);
+    }
+
+    #[test]
+    fn fill_rows_test() {
+        // Pseudo-random caves of horizontal and vertical paths under the source.
+        let mut seed = 12345u64;
+        let mut random = |n: i64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) % n as u64) as i64
+        };
+        for _ in 0..30 {
+            let mut input = vec![];
+            for _ in 0..(1 + random(8)) {
+                let mut p = (480 + random(40), 1 + random(30));
+                let mut path = vec![format!("{},{}", p.0, p.1)];
+                for _ in 0..random(4) {
+                    let step = random(9) - 4;
+                    if random(2) == 0 {
+                        p.0 += step;
+                    } else {
+                        p.1 = (p.1 + step).max(1);
+                    }
+                    path.push(format!("{},{}", p.0, p.1));
+                }
+                input.push(path.join(" -> "));
+            }
+            assert_eq!(
+                part2(input.iter()).unwrap(),
+                simulate_part2(input.iter()).unwrap(),
+                "{:?}",
+                input
+            );
+        }
+
+        // A deep cave: a ledge of 5 rocks shelters 3 + 1 cells below it, and a single rock
+        // sits near the bottom.
+        let input = ["498,3 -> 502,3", "500,100000 -> 500,100000"];
+        let rows = 100002;
+        assert_eq!(part2(input.iter()).unwrap(), rows * rows - 5 - 4 - 1);
+    }
+
+    #[test]
+    fn validation_test() {
+        let error = |input: &[&str]| part1(input.iter()).unwrap_err().to_string();
+        assert_eq!(
+            error(&["498,4 -> 498,6", "503,4 -> 502,5"]),
+            "Input error: line 2: diagonal segment from 503,4 to 502,5"
+        );
+        assert_eq!(
+            error(&["498,4 -> 498,6", ""]),
+            "Input error: line 2: empty rock path"
+        );
+        assert_eq!(
+            error(&["498,4 -> 4986"]),
+            "Input error: line 1: expected `x,y` but found `4986`"
+        );
+        assert_eq!(
+            error(&["498,4 -> 498,x"]),
+            "Input error: line 1: invalid coordinate `x` in `498,x`"
+        );
+        assert_eq!(
+            error(&["498,4 -> 498,-1"]),
+            "Input error: line 1: rock at 498,-1 is above the source"
+        );
+        assert_eq!(
+            error(&["498,4 ->"]),
+            "Input error: line 1: missing point 2 of the path"
+        );
+        assert_eq!(
+            error(&["-> 498,4"]),
+            "Input error: line 1: missing point 1 of the path"
+        );
+        assert_eq!(
+            error(&["498,4 -> -> 498,6"]),
+            "Input error: line 1: missing point 2 of the path"
+        );
+        assert_eq!(part1(["498,4->498,6 ->  496,6"].iter()).unwrap(), 0);
+        assert_eq!(part1(["500,5"].iter()).unwrap(), 0);
+        assert_eq!(part2(["500,5"].iter()).unwrap(), 49 - 1);
+    }
+
+    #[test]
+    fn render_test() {
+        let input = [
+            "498,4 -> 498,6 -> 496,6",
+            "503,4 -> 502,4 -> 502,9 -> 494,9",
+        ];
+        assert_eq!(
+            render_cave(input.iter()).unwrap(),
+            [
+                "......+...",
+                "..........",
+                "..........",
+                "..........",
+                "....#...##",
+                "....#...#.",
+                "..###...#.",
+                "........#.",
+                "........#.",
+                "#########.",
+            ]
+        );
+    }
+
+    #[test]
+    fn engine_test() {
+        let mut floor = Grid::new(5, 5, false);
+        for j in 0..5 {
+            floor[(4, j)] = true;
+        }
+
+        // Sand that can only fall straight down stacks up under each source.
+        let engine = SandEngine {
+            moves: vec![Coord { i: 1, j: 0 }],
+            sources: vec![Coord { i: 0, j: 1 }, Coord { i: 1, j: 3 }],
+        };
+        let mut grains = vec![];
+        let (grid, stats) = engine.run(floor.clone(), |grain, _| {
+            grains.push((grain.source, grain.rest))
+        });
+        assert_eq!(
+            stats,
+            SandStats {
+                grains: 7,
+                per_source: vec![4, 3],
+                per_column: vec![0, 4, 0, 3, 0],
+            }
+        );
+        assert_eq!(
+            grains[..3],
+            [
+                (0, Some(Coord { i: 3, j: 1 })),
+                (1, Some(Coord { i: 3, j: 3 })),
+                (0, Some(Coord { i: 2, j: 1 })),
+            ]
+        );
+        assert_eq!(grains.len(), 7);
+        assert_eq!(
+            show_bool_grid(&grid),
+            [".#...", ".#.#.", ".#.#.", ".#.#.", "#####"]
+        );
+
+        // Grains pile up around a single source until it is covered.
+        let engine = SandEngine::new(vec![Coord { i: 2, j: 2 }]);
+        let (grid, stats) = engine.run(floor, |_, _| {});
+        assert_eq!(stats.grains, 4);
+        assert_eq!(stats.per_column, [0, 1, 2, 1, 0]);
+        assert_eq!(
+            show_bool_grid(&grid),
+            [".....", ".....", "..#..", ".###.", "#####"]
+        );
+
+        // Without the floor the first grain falls out of the grid.
+        let mut escaped = 0;
+        let (_, stats) = engine.run(Grid::new(5, 5, false), |grain, _| {
+            escaped += grain.rest.is_none() as i64
+        });
+        assert_eq!((stats.grains, escaped), (0, 1));
+
+        // Sources that start covered or outside the grid drop nothing.
+        let engine = SandEngine::new(vec![Coord { i: 4, j: 0 }, Coord { i: 9, j: 0 }]);
+        assert_eq!(engine.run(Grid::new(5, 5, true), |_, _| {}).1.grains, 0);
     }
 }
 
 fn main() {
+    // Pass --render to draw the parsed cave before simulating.
+    if std::env::args().any(|a| a == "--render") {
+        let cave = render_cave(read_aoc_lines!());
+        print!(
+            "{}\n",
+            cave.map_or_else(|e| e.to_string(), |rows| rows.join("\n"))
+        );
+    }
     print!("{:?}\n", part1(read_aoc_lines!()));
     print!("{:?}\n", part2(read_aoc_lines!()));
 }
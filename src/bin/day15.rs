@@ -1,11 +1,12 @@
-use std::cmp::{max, min};
-
-use advent_2022::{read_aoc_lines, Coord, InputIterator, OptionUtils};
+use advent_2022::{
+    check, read_aoc_lines, Coord, Diamond, InputError, InputIterator, IntervalSet, OptionUtils,
+};
 use anyhow::Result;
+use std::collections::HashSet;
 
 struct Sensor {
-    loc: Coord,
-    dist: i64,
+    range: Diamond,
+    beacon: Coord,
 }
 
 fn parse_coord(s: &str) -> Result<Coord> {
@@ -22,93 +23,121 @@ fn parse_line(s: &str) -> Result<Sensor> {
     let sensor_loc = parse_coord(split.next().ok_or_err()?.split_once(":").ok_or_err()?.0)?;
     let beacon_loc = parse_coord(split.next().ok_or_err()?)?;
     Ok(Sensor {
-        loc: sensor_loc,
-        dist: (sensor_loc.i - beacon_loc.i).abs() + (sensor_loc.j - beacon_loc.j).abs(),
+        range: Diamond::through(sensor_loc, beacon_loc),
+        beacon: beacon_loc,
     })
 }
 
-fn sensor_to_coverage(sensor: &Sensor, iline: i64) -> Option<(i64, i64)> {
-    let remaining_distance = sensor.dist - (sensor.loc.i - iline).abs();
-    if remaining_distance < 0 {
-        None
-    } else {
-        Some((
-            sensor.loc.j - remaining_distance,
-            sensor.loc.j + remaining_distance,
-        ))
-    }
+// Counts the positions on `row` where a beacon cannot be: those covered by some sensor,
+// except for the known beacons themselves.
+fn part1<I: InputIterator>(input: I, row: i64) -> Result<i64> {
+    let sensors = input
+        .map(|s| parse_line(s.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    let coverage: IntervalSet = sensors.iter().filter_map(|s| s.range.row(row)).collect();
+    let beacons: HashSet<i64> = sensors
+        .iter()
+        .filter(|s| s.beacon.i == row && coverage.contains(s.beacon.j))
+        .map(|s| s.beacon.j)
+        .collect();
+    Ok(coverage.total_length() - beacons.len() as i64)
 }
 
-fn do_part1<I: InputIterator>(input: I, iline: i64) -> Result<i64> {
-    let mut coverage = input
-        .filter_map(|s| {
-            let sensor = match parse_line(s.as_ref()) {
-                Err(e) => return Some(Err(e)),
-                Ok(s) => s,
-            };
-            sensor_to_coverage(&sensor, iline).map(|x| Ok(x))
+// Rotating by 45 degrees (u = x + y, v = x - y) turns every sensor's diamond into an
+// axis-aligned square, whose edges are the lines just inside the diamond's boundary.
+// The u edges of all sensors cut the plane into slabs in which every u sees the same
+// covered v intervals, so each gap is a rectangle between the u edges of one pair of
+// sensors and the v edges of another. Each rectangle is intersected with the rotated
+// search area, and only the points in that intersection are visited.
+// Returns every point in the area from `min` to `max` (inclusive) that no sensor covers.
+fn uncovered_points(sensors: &[Sensor], min: Coord, max: Coord) -> Vec<Coord> {
+    let squares: Vec<_> = sensors
+        .iter()
+        .map(|s| {
+            let Diamond { center, radius } = s.range;
+            let (u, v) = (center.j + center.i, center.j - center.i);
+            ((u - radius, u + radius), (v - radius, v + radius))
         })
-        .collect::<Result<Vec<_>>>()?;
-
-    coverage.sort();
-
-    let mut total_coverage = 0;
-    let null_cover = (i64::MIN, i64::MIN);
-    let mut current_cover = null_cover;
-
-    for cover in coverage.iter() {
-        if (cover.0 - current_cover.1) > 1 {
-            // what we have is disjoint from the rest
-            total_coverage += current_cover.1 - current_cover.0;
-            current_cover = *cover;
-        } else {
-            // what we have can be combined with the next cover
-            current_cover = (min(cover.0, current_cover.0), max(cover.1, current_cover.1));
-        }
-    }
-    total_coverage += current_cover.1 - current_cover.0;
+        .collect();
+    let (u_min, u_max) = (min.j + min.i, max.j + max.i);
+    let (v_min, v_max) = (min.j - max.i, max.j - min.i);
 
-    Ok(total_coverage)
-}
-
-fn part1<I: InputIterator>(input: I) -> Result<i64> {
-    do_part1(input, 2000000)
-}
+    let mut edges: Vec<i64> = squares
+        .iter()
+        .flat_map(|((start, end), _)| [*start, end + 1])
+        .chain([u_min, u_max + 1])
+        .filter(|u| (u_min..=u_max + 1).contains(u))
+        .collect();
+    edges.sort();
+    edges.dedup();
 
-fn do_part2<I: InputIterator>(input: I, max_coord: i64) -> Result<i64> {
-    let sensors = input
-        .map(|s| parse_line(s.as_ref()))
-        .collect::<Result<Vec<_>>>()?;
-    for row in 0..=max_coord {
-        let mut coverage: Vec<_> = sensors
+    let mut points = vec![];
+    for slab in edges.windows(2) {
+        let (first_u, last_u) = (slab[0], slab[1] - 1);
+        let covered: IntervalSet = squares
             .iter()
-            .filter_map(|s| sensor_to_coverage(s, row))
+            .filter(|((start, end), _)| *start <= first_u && last_u <= *end)
+            .map(|(_, v)| *v)
             .collect();
-        coverage.sort();
-        let mut extent = 0;
-        for cover in coverage.iter() {
-            if (cover.0 - extent) > 1 {
-                // we found a gap
-                return Ok(row + (extent + 1) * 4000000);
-            } else {
-                extent = max(extent, cover.1);
+        let gaps = IntervalSet::from_iter([(v_min, v_max)]).difference(&covered);
+        if gaps.is_empty() {
+            continue;
+        }
+        for &(first_v, last_v) in gaps.intervals() {
+            // x = (u + v) / 2 and y = (u - v) / 2 must lie inside the search area, which
+            // bounds v by 2 * min.j - u and u - 2 * max.i from below and by 2 * max.j - u
+            // and u - 2 * min.i from above. The u where that range meets the gap:
+            let start = first_u.max(2 * min.j - last_v).max(first_v + 2 * min.i);
+            let end = last_u.min(last_v + 2 * max.i).min(2 * max.j - first_v);
+            for u in start..=end {
+                let low = first_v.max(2 * min.j - u).max(u - 2 * max.i);
+                let high = last_v.min(2 * max.j - u).min(u - 2 * min.i);
+                // u and v of an integer point always have the same parity.
+                let low = low + (low - u).rem_euclid(2);
+                for v in (low..=high).step_by(2) {
+                    points.push(Coord {
+                        i: (u - v) / 2,
+                        j: (u + v) / 2,
+                    });
+                }
             }
         }
     }
-    Ok(0)
+    points.sort_by_key(|c| (c.i, c.j));
+    points
 }
 
-fn part2<I: InputIterator>(input: I) -> Result<i64> {
-    do_part2(input, 4000000)
+fn part2<I: InputIterator>(input: I, max_coord: i64) -> Result<i64> {
+    let sensors = input
+        .map(|s| parse_line(s.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    let points = uncovered_points(
+        &sensors,
+        Coord { i: 0, j: 0 },
+        Coord {
+            i: max_coord,
+            j: max_coord,
+        },
+    );
+    check(points.len() == 1, || {
+        InputError::new(format!(
+            "Expected exactly one uncovered point, found {}",
+            points.len()
+        ))
+    })?;
+    Ok(points[0].i + points[0].j * 4000000)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::do_part1;
-    use crate::do_part2;
-    #[test]
-    fn day15_test() {
-        let input = vec![
+    use crate::parse_line;
+    use crate::part1;
+    use crate::part2;
+    use crate::uncovered_points;
+    use advent_2022::Coord;
+
+    fn example() -> Vec<&'static str> {
+        vec![
             "Sensor at x=2, y=18: closest beacon is at x=-2, y=15",
             "Sensor at x=9, y=16: closest beacon is at x=10, y=16",
             "Sensor at x=13, y=2: closest beacon is at x=15, y=3",
@@ -123,13 +152,104 @@ mod tests {
             "Sensor at x=16, y=7: closest beacon is at x=15, y=3",
             "Sensor at x=14, y=3: closest beacon is at x=15, y=3",
             "Sensor at x=20, y=1: closest beacon is at x=15, y=3",
+        ]
+    }
+
+    #[test]
+    fn day15_test() {
+        let input = example();
+        assert_eq!(part1(input.iter(), 10).unwrap(), 26);
+        assert_eq!(part2(input.iter(), 20).unwrap(), 56000011);
+    }
+
+    #[test]
+    fn beacon_test() {
+        let input = example();
+        let sensors: Vec<_> = input.iter().map(|s| parse_line(s).unwrap()).collect();
+        for row in -12..=32 {
+            let excluded = (-20..=50)
+                .filter(|&j| {
+                    sensors.iter().any(|s| s.range.contains((row, j)))
+                        && sensors.iter().all(|s| s.beacon != Coord { i: row, j })
+                })
+                .count();
+            assert_eq!(
+                part1(input.iter(), row).unwrap(),
+                excluded as i64,
+                "row {}",
+                row
+            );
+        }
+
+        // Several sensors sharing a beacon on the row only remove it once.
+        let input = [
+            "Sensor at x=0, y=0: closest beacon is at x=2, y=0",
+            "Sensor at x=4, y=0: closest beacon is at x=2, y=0",
         ];
-        assert_eq!(do_part1(input.iter(), 10).unwrap(), 26);
-        assert_eq!(do_part2(input.iter(), 20).unwrap(), 56000011);
+        assert_eq!(part1(input.iter(), 0).unwrap(), 8);
+        assert_eq!(part1(input.iter(), 1).unwrap(), 6);
     }
+
+    #[test]
+    fn uncovered_test() {
+        let sensors: Vec<_> = example().iter().map(|s| parse_line(s).unwrap()).collect();
+        let brute_force = |min: Coord, max: Coord| {
+            let mut points = vec![];
+            for i in min.i..=max.i {
+                for j in min.j..=max.j {
+                    if sensors.iter().all(|s| !s.range.contains((i, j))) {
+                        points.push(Coord { i, j });
+                    }
+                }
+            }
+            points
+        };
+
+        let (min, max) = (Coord { i: 0, j: 0 }, Coord { i: 20, j: 20 });
+        assert_eq!(
+            uncovered_points(&sensors, min, max),
+            [Coord { i: 11, j: 14 }]
+        );
+
+        for (min, max) in [
+            ((-10, -10), (30, 30)),
+            ((-3, 5), (7, 28)),
+            ((11, 14), (11, 14)),
+            ((12, 0), (11, 0)),
+        ] {
+            let (min, max) = (min.into(), max.into());
+            assert_eq!(uncovered_points(&sensors, min, max), brute_force(min, max));
+        }
+
+        // A single sensor leaves the corners of its bounding box uncovered.
+        let sensor = parse_line("Sensor at x=0, y=0: closest beacon is at x=1, y=0").unwrap();
+        assert_eq!(
+            uncovered_points(&[sensor], (-1, -1).into(), (1, 1).into()),
+            [
+                (-1, -1).into(),
+                (-1, 1).into(),
+                (1, -1).into(),
+                (1, 1).into()
+            ]
+        );
+    }
+}
+
+// The puzzle input uses row 2000000 and a search area of 0..=4000000.
+fn parse_args() -> Result<(i64, i64)> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    check(args.len() == 2, || {
+        InputError::new("Usage: day15 <row> <max coordinate>")
+    })?;
+    Ok((args[0].parse()?, args[1].parse()?))
 }
 
 fn main() {
-    print!("{:?}\n", part1(read_aoc_lines!()));
-    print!("{:?}\n", part2(read_aoc_lines!()));
+    match parse_args() {
+        Err(e) => print!("{}\n", e),
+        Ok((row, max_coord)) => {
+            print!("{:?}\n", part1(read_aoc_lines!(), row));
+            print!("{:?}\n", part2(read_aoc_lines!(), max_coord));
+        }
+    }
 }
@@ -1,13 +1,15 @@
-use std::{
-    cmp::max,
-    collections::{HashMap, HashSet, VecDeque},
-};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use advent_2022::{read_aoc_lines, InputIterator, OptionUtils};
 use anyhow::Result;
 
+// Raw tunnel graph keyed by valve name: compressed index (for AA and nonzero valves), flow rate
+// and connections.
+type Tunnels = HashMap<String, (Option<usize>, i64, Vec<String>)>;
+
 #[derive(Default, Clone)]
 struct Node {
+    name: String,
     flow_rate: i64,
     distances: Vec<i64>,
 }
@@ -16,11 +18,7 @@ fn split_between<'a>(source: &'a str, left: &str, right: &str) -> Option<&'a str
     Some(source.split_once(left)?.1.split_once(right)?.0)
 }
 
-fn find_all_distances(
-    init_graph: &HashMap<String, (Option<usize>, i64, Vec<String>)>,
-    node: &str,
-    highest_index: usize,
-) -> Vec<i64> {
+fn find_all_distances(init_graph: &Tunnels, node: &str, highest_index: usize) -> Vec<i64> {
     let mut visited = HashSet::from([node]);
     let mut nodes = VecDeque::from([(node, 0)]);
     let mut result = vec![0; highest_index + 1];
@@ -44,10 +42,10 @@ fn find_all_distances(
     result
 }
 
-fn parse_graph<I: InputIterator>(input: I) -> Result<Vec<Node>> {
+fn parse_tunnels<I: InputIterator>(input: I) -> Result<Tunnels> {
     let mut index = 0;
 
-    let init_graph = input
+    input
         .map(|l| {
             let line = l.as_ref();
             let name = split_between(line, "Valve ", " ").ok_or_err()?.to_string();
@@ -68,134 +66,423 @@ fn parse_graph<I: InputIterator>(input: I) -> Result<Vec<Node>> {
             };
             Ok((name, (i, flow_rate, connections)))
         })
-        .collect::<Result<HashMap<_, _>>>()?;
+        .collect()
+}
 
+fn compress_graph(init_graph: &Tunnels) -> Vec<Node> {
+    let index = init_graph.values().filter_map(|v| v.0).max().unwrap_or(0);
     let mut final_graph: Vec<Node> = vec![Default::default(); index + 1];
 
     for (k, v) in init_graph.iter() {
         let i = if let Some(i) = v.0 { i } else { continue };
+        final_graph[i].name = k.clone();
         final_graph[i].flow_rate = v.1;
-        final_graph[i].distances = find_all_distances(&init_graph, k, index);
+        final_graph[i].distances = find_all_distances(init_graph, k, index);
     }
 
-    Ok(final_graph)
+    final_graph
+}
+
+fn parse_graph<I: InputIterator>(input: I) -> Result<Vec<Node>> {
+    Ok(compress_graph(&parse_tunnels(input)?))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Opening {
+    agent: usize,
+    valve: usize,
+    minute: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Plan {
+    pressure: i64,
+    openings: Vec<Opening>,
 }
 
-fn part1_helper(
-    graph: &Vec<Node>,
+// Records, for every set of opened valves, the best plan for a single agent that opens exactly
+// those valves (in some order) within the time limit.
+fn visit_subsets(
+    graph: &[Node],
     current_index: usize,
-    valves_on: &mut Vec<bool>,
-    num_valves_on: usize,
+    valves_on: usize,
+    minutes: i64,
     minutes_left: i64,
-) -> i64 {
-    if num_valves_on == valves_on.len() {
-        return 0;
+    current: &mut Plan,
+    best: &mut [Plan],
+) {
+    if current.pressure > best[valves_on].pressure {
+        best[valves_on] = current.clone();
+    }
+    for (neighbor, distance) in graph[current_index].distances.iter().enumerate().skip(1) {
+        let bit = 1 << (neighbor - 1);
+        if valves_on & bit != 0 {
+            continue;
+        }
+        let remaining = minutes_left - distance - 1;
+        if remaining <= 0 {
+            continue;
+        }
+        let added = remaining * graph[neighbor].flow_rate;
+        current.pressure += added;
+        current.openings.push(Opening {
+            agent: 0,
+            valve: neighbor,
+            minute: minutes - remaining,
+        });
+        visit_subsets(
+            graph,
+            neighbor,
+            valves_on | bit,
+            minutes,
+            remaining,
+            current,
+            best,
+        );
+        current.openings.pop();
+        current.pressure -= added;
+    }
+}
+
+// Best plan for every subset of the nonzero valves, indexed by bitmask (bit i - 1 is valve i,
+// since valve 0 is the starting point). Each entry is the best over all of its subsets.
+fn best_per_subset(graph: &[Node], minutes: i64) -> Vec<Plan> {
+    let num_valves = graph.len() - 1;
+    let mut best = vec![Plan::default(); 1 << num_valves];
+    visit_subsets(
+        graph,
+        0,
+        0,
+        minutes,
+        minutes,
+        &mut Plan::default(),
+        &mut best,
+    );
+    for bit in 0..num_valves {
+        for mask in 0..best.len() {
+            if mask & (1 << bit) != 0 && best[mask ^ (1 << bit)].pressure > best[mask].pressure {
+                best[mask] = best[mask ^ (1 << bit)].clone();
+            }
+        }
     }
-    if minutes_left <= 0 {
-        return 0;
+    best
+}
+
+// Agents never benefit from opening the same valve, so each one is handed a disjoint subset.
+// The returned openings are ordered by agent, then by minute.
+fn max_pressure(graph: &[Node], minutes: i64, agents: usize) -> Plan {
+    if agents == 0 {
+        return Plan::default();
+    }
+    let best = best_per_subset(graph, minutes);
+    let mut combined: Vec<i64> = best.iter().map(|p| p.pressure).collect();
+    // For each additional agent, the part of each mask handed to the agents before it.
+    let mut splits: Vec<Vec<usize>> = vec![];
+    for _ in 1..agents {
+        let (pressures, split) = (0..best.len())
+            .map(|mask| {
+                let mut result = (0, 0);
+                let mut sub = mask;
+                loop {
+                    let pressure = combined[sub] + best[mask ^ sub].pressure;
+                    if pressure > result.0 {
+                        result = (pressure, sub);
+                    }
+                    if sub == 0 {
+                        break result;
+                    }
+                    sub = (sub - 1) & mask;
+                }
+            })
+            .unzip();
+        combined = pressures;
+        splits.push(split);
+    }
+
+    let mut mask = best.len() - 1;
+    let mut per_agent = vec![];
+    for split in splits.iter().rev() {
+        per_agent.push(mask ^ split[mask]);
+        mask = split[mask];
     }
+    per_agent.push(mask);
 
-    graph[current_index]
-        .distances
+    let openings = per_agent
         .iter()
+        .rev()
         .enumerate()
-        .map(|(neighbor, distance)| {
-            if valves_on[neighbor] {
-                return 0;
-            }
-            valves_on[neighbor] = true;
-            let rest = part1_helper(
-                graph,
-                neighbor,
-                valves_on,
-                num_valves_on + 1,
-                minutes_left - *distance - 1,
-            );
-            let result = rest + (minutes_left - *distance - 1) * graph[neighbor].flow_rate;
-            valves_on[neighbor] = false;
-            result
+        .flat_map(|(agent, mask)| {
+            best[*mask]
+                .openings
+                .iter()
+                .map(move |o| Opening { agent, ..o.clone() })
         })
-        .max()
-        .unwrap()
+        .collect();
+    Plan {
+        pressure: combined[best.len() - 1],
+        openings,
+    }
 }
 
-fn part1<I: InputIterator>(input: I) -> Result<i64> {
-    let graph = parse_graph(input)?;
-    let mut valves_on = vec![false; graph.len()];
-    valves_on[0] = true;
-    return Ok(part1_helper(&graph, 0, &mut valves_on, 1, 30));
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Move(String),
+    Open(String),
 }
 
-fn part2_helper(
-    graph: &Vec<Node>,
-    current_index: (usize, usize),
-    valves_on: &mut Vec<bool>,
-    num_valves_on: usize,
-    minutes_left: (i64, i64),
-) -> i64 {
-    if num_valves_on == valves_on.len() {
-        return 0;
+fn shortest_path(tunnels: &Tunnels, from: &str, to: &str) -> Vec<String> {
+    let mut previous = HashMap::from([(from, from)]);
+    let mut nodes = VecDeque::from([from]);
+    while let Some(name) = nodes.pop_front() {
+        if name == to {
+            break;
+        }
+        for nn in tunnels[name].2.iter() {
+            if !previous.contains_key(nn.as_str()) {
+                previous.insert(nn, name);
+                nodes.push_back(nn);
+            }
+        }
     }
-    if minutes_left.0 == 0 && minutes_left.1 == 0 {
-        return 0;
+    let mut path = vec![];
+    let mut current = to;
+    while current != from {
+        path.push(current.to_string());
+        current = previous[current];
     }
+    path.reverse();
+    path
+}
 
-    (1..valves_on.len())
-        .map(|i| {
-            if valves_on[i] {
-                return 0;
+// One action per minute for each agent, starting from minute 1, in the input's valve names.
+fn plan_routes(tunnels: &Tunnels, graph: &[Node], plan: &Plan, agents: usize) -> Vec<Vec<Action>> {
+    (0..agents)
+        .map(|agent| {
+            let mut current = &graph[0].name;
+            let mut actions = vec![];
+            for opening in plan.openings.iter().filter(|o| o.agent == agent) {
+                let target = &graph[opening.valve].name;
+                actions.extend(
+                    shortest_path(tunnels, current, target)
+                        .into_iter()
+                        .map(Action::Move),
+                );
+                actions.push(Action::Open(target.clone()));
+                current = target;
             }
+            actions
+        })
+        .collect()
+}
 
-            let my_minutes = minutes_left.0 - graph[current_index.0].distances[i] - 1;
-            let ele_minutes = minutes_left.1 - graph[current_index.1].distances[i] - 1;
+fn join_names(names: &[&str]) -> String {
+    match names {
+        [] => String::new(),
+        [a] => a.to_string(),
+        [a, b] => format!("{} and {}", a, b),
+        [rest @ .., last] => format!("{}, and {}", rest.join(", "), last),
+    }
+}
 
-            if max(my_minutes, ele_minutes) < 0 {
-                return 0;
+// Reproduces the puzzle's minute-by-minute narration of a plan.
+fn narrate(
+    tunnels: &Tunnels,
+    graph: &[Node],
+    plan: &Plan,
+    minutes: i64,
+    agents: usize,
+) -> Vec<String> {
+    let routes = plan_routes(tunnels, graph, plan, agents);
+    let mut result = vec![];
+    for minute in 1..=minutes {
+        result.push(format!("== Minute {} ==", minute));
+        let mut open: Vec<_> = plan
+            .openings
+            .iter()
+            .filter(|o| o.minute < minute)
+            .map(|o| &graph[o.valve])
+            .collect();
+        open.sort_by(|a, b| a.name.cmp(&b.name));
+        let released: i64 = open.iter().map(|n| n.flow_rate).sum();
+        let names: Vec<_> = open.iter().map(|n| n.name.as_str()).collect();
+        result.push(match names.len() {
+            0 => "No valves are open.".to_string(),
+            1 => format!(
+                "Valve {} is open, releasing {} pressure.",
+                names[0], released
+            ),
+            _ => format!(
+                "Valves {} are open, releasing {} pressure.",
+                join_names(&names),
+                released
+            ),
+        });
+        for (agent, route) in routes.iter().enumerate() {
+            let (subject, s) = match agent {
+                0 => ("You".to_string(), ""),
+                1 => ("The elephant".to_string(), "s"),
+                _ => (format!("Elephant {}", agent), "s"),
+            };
+            match route.get((minute - 1) as usize) {
+                Some(Action::Move(name)) => {
+                    result.push(format!("{} move{} to valve {}.", subject, s, name))
+                }
+                Some(Action::Open(name)) => {
+                    result.push(format!("{} open{} valve {}.", subject, s, name))
+                }
+                None => {}
             }
+        }
+        result.push(String::new());
+    }
+    result
+}
 
-            valves_on[i] = true;
+const ROUTE_COLORS: [&str; 4] = ["red", "blue", "darkgreen", "orange"];
 
-            let result = if my_minutes >= ele_minutes {
-                let rest = part2_helper(
-                    graph,
-                    (i, current_index.1),
-                    valves_on,
-                    num_valves_on + 1,
-                    (my_minutes, minutes_left.1),
-                );
-                rest + my_minutes * graph[i].flow_rate
-            } else {
-                let rest = part2_helper(
-                    graph,
-                    (current_index.0, i),
-                    valves_on,
-                    num_valves_on + 1,
-                    (minutes_left.0, ele_minutes),
-                );
-                rest + ele_minutes * graph[i].flow_rate
+fn route_color(agent: usize) -> &'static str {
+    ROUTE_COLORS[agent % ROUTE_COLORS.len()]
+}
+
+// Graphviz rendering of the raw tunnels, labelled with flow rates; edges walked by each agent's
+// route and the valves it opens are drawn in that agent's colour.
+fn tunnels_to_dot(tunnels: &Tunnels, routes: &[Vec<Action>]) -> String {
+    let mut walked = HashMap::new();
+    let mut opened = HashMap::new();
+    for (agent, route) in routes.iter().enumerate() {
+        let mut current = "AA";
+        for action in route.iter() {
+            match action {
+                Action::Move(name) => {
+                    let edge = if current < name.as_str() {
+                        (current, name.as_str())
+                    } else {
+                        (name.as_str(), current)
+                    };
+                    walked.entry(edge).or_insert(agent);
+                    current = name;
+                }
+                Action::Open(name) => {
+                    opened.insert(name.as_str(), agent);
+                }
+            }
+        }
+    }
+
+    let mut names: Vec<_> = tunnels.keys().collect();
+    names.sort();
+    let mut result = vec!["graph tunnels {".to_string()];
+    for name in names.iter() {
+        let style = match opened.get(name.as_str()) {
+            Some(agent) => format!(", style=filled, fillcolor={}", route_color(*agent)),
+            None => String::new(),
+        };
+        result.push(format!(
+            "    {} [label=\"{}\\n{}\"{}];",
+            name, name, tunnels[*name].1, style
+        ));
+    }
+    for name in names.iter() {
+        let mut connections: Vec<_> = tunnels[*name]
+            .2
+            .iter()
+            .filter(|nn| name.as_str() < nn.as_str())
+            .collect();
+        connections.sort();
+        for nn in connections {
+            let style = match walked.get(&(name.as_str(), nn.as_str())) {
+                Some(agent) => format!(" [color={}, penwidth=3]", route_color(*agent)),
+                None => String::new(),
             };
+            result.push(format!("    {} -- {}{};", name, nn, style));
+        }
+    }
+    result.push("}".to_string());
+    result.join("\n")
+}
 
-            valves_on[i] = false;
-            result
-        })
-        .max()
-        .unwrap()
+// Graphviz rendering of the compressed graph: AA and the nonzero valves, with edges weighted by
+// distance. Each agent's sequence of openings is overlaid as directed edges in its colour.
+fn compressed_to_dot(graph: &[Node], plan: &Plan) -> String {
+    let mut result = vec!["digraph valves {".to_string()];
+    for node in graph.iter() {
+        result.push(format!(
+            "    {} [label=\"{}\\n{}\"];",
+            node.name, node.name, node.flow_rate
+        ));
+    }
+    for (i, node) in graph.iter().enumerate() {
+        for (j, distance) in node.distances.iter().enumerate().skip(i + 1) {
+            result.push(format!(
+                "    {} -> {} [label={}, dir=none, color=gray];",
+                node.name, graph[j].name, distance
+            ));
+        }
+    }
+    let mut previous: HashMap<usize, usize> = HashMap::new();
+    for opening in plan.openings.iter() {
+        let from = previous.insert(opening.agent, opening.valve).unwrap_or(0);
+        result.push(format!(
+            "    {} -> {} [label=\"{}\", color={}, penwidth=3];",
+            graph[from].name,
+            graph[opening.valve].name,
+            opening.minute,
+            route_color(opening.agent)
+        ));
+    }
+    result.push("}".to_string());
+    result.join("\n")
+}
+
+fn part1<I: InputIterator>(input: I) -> Result<i64> {
+    let graph = parse_graph(input)?;
+    Ok(max_pressure(&graph, 30, 1).pressure)
 }
 
 fn part2<I: InputIterator>(input: I) -> Result<i64> {
     let graph = parse_graph(input)?;
-    let mut valves_on = vec![false; graph.len()];
-    valves_on[0] = true;
-    return Ok(part2_helper(&graph, (0, 0), &mut valves_on, 1, (26, 26)));
+    Ok(max_pressure(&graph, 26, 2).pressure)
+}
+
+// Writes the best part 2 plan as `<prefix>.txt` (the narration) along with the tunnels and
+// the compressed valve graph, with the routes highlighted, as `<prefix>-tunnels.dot` and
+// `<prefix>-valves.dot`.
+fn export_part2<I: InputIterator>(input: I, prefix: &str) -> Result<()> {
+    let tunnels = parse_tunnels(input)?;
+    let graph = compress_graph(&tunnels);
+    let plan = max_pressure(&graph, 26, 2);
+    let routes = plan_routes(&tunnels, &graph, &plan, 2);
+    let narration = narrate(&tunnels, &graph, &plan, 26, 2).join("\n") + "\n";
+    std::fs::write(format!("{}.txt", prefix), narration)?;
+    std::fs::write(
+        format!("{}-tunnels.dot", prefix),
+        tunnels_to_dot(&tunnels, &routes),
+    )?;
+    std::fs::write(
+        format!("{}-valves.dot", prefix),
+        compressed_to_dot(&graph, &plan),
+    )?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
+    use crate::compress_graph;
+    use crate::compressed_to_dot;
+    use crate::export_part2;
+    use crate::max_pressure;
+    use crate::narrate;
+    use crate::parse_graph;
+    use crate::parse_tunnels;
     use crate::part1;
     use crate::part2;
-    #[test]
-    fn dayn_test() {
-        let input = vec![
+    use crate::plan_routes;
+    use crate::tunnels_to_dot;
+
+    fn example() -> Vec<&'static str> {
+        vec![
             "Valve AA has flow rate=0; tunnels lead to valves DD, II, BB",
             "Valve BB has flow rate=13; tunnels lead to valves CC, AA",
             "Valve CC has flow rate=2; tunnels lead to valves DD, BB",
@@ -206,13 +493,125 @@ mod tests {
             "Valve HH has flow rate=22; tunnel leads to valve GG",
             "Valve II has flow rate=0; tunnels lead to valves AA, JJ",
             "Valve JJ has flow rate=21; tunnel leads to valve II",
-        ];
+        ]
+    }
+
+    #[test]
+    fn dayn_test() {
+        let input = example();
         assert_eq!(part1(input.iter()).unwrap(), 1651);
         assert_eq!(part2(input.iter()).unwrap(), 1707);
     }
+
+    #[test]
+    fn schedule_test() {
+        let graph = parse_graph(example().iter()).unwrap();
+        for (minutes, agents) in [(30, 1), (26, 2), (26, 3), (10, 4)] {
+            let plan = max_pressure(&graph, minutes, agents);
+            let released: i64 = plan
+                .openings
+                .iter()
+                .map(|o| (minutes - o.minute) * graph[o.valve].flow_rate)
+                .sum();
+            assert_eq!(released, plan.pressure);
+            let valves: HashSet<_> = plan.openings.iter().map(|o| o.valve).collect();
+            assert_eq!(valves.len(), plan.openings.len());
+            assert!(plan.openings.iter().all(|o| o.agent < agents));
+        }
+        let plan = max_pressure(&graph, 30, 1);
+        let minutes: Vec<_> = plan.openings.iter().map(|o| o.minute).collect();
+        assert_eq!(minutes, vec![2, 5, 9, 17, 21, 24]);
+    }
+
+    #[test]
+    fn narrate_test() {
+        let tunnels = parse_tunnels(example().iter()).unwrap();
+        let graph = compress_graph(&tunnels);
+        let plan = max_pressure(&graph, 30, 1);
+        let lines = narrate(&tunnels, &graph, &plan, 30, 1);
+        assert_eq!(
+            lines[..20],
+            [
+                "== Minute 1 ==",
+                "No valves are open.",
+                "You move to valve DD.",
+                "",
+                "== Minute 2 ==",
+                "No valves are open.",
+                "You open valve DD.",
+                "",
+                "== Minute 3 ==",
+                "Valve DD is open, releasing 20 pressure.",
+                "You move to valve CC.",
+                "",
+                "== Minute 4 ==",
+                "Valve DD is open, releasing 20 pressure.",
+                "You move to valve BB.",
+                "",
+                "== Minute 5 ==",
+                "Valve DD is open, releasing 20 pressure.",
+                "You open valve BB.",
+                "",
+            ]
+        );
+        assert_eq!(
+            lines[lines.len() - 3..],
+            [
+                "== Minute 30 ==",
+                "Valves BB, CC, DD, EE, HH, and JJ are open, releasing 81 pressure.",
+                "",
+            ]
+        );
+
+        let plan = max_pressure(&graph, 26, 2);
+        let lines = narrate(&tunnels, &graph, &plan, 26, 2);
+        assert_eq!(lines.len(), 26 * 3 + 20);
+    }
+
+    #[test]
+    fn dot_test() {
+        let tunnels = parse_tunnels(example().iter()).unwrap();
+        let graph = compress_graph(&tunnels);
+        let plan = max_pressure(&graph, 30, 1);
+        let routes = plan_routes(&tunnels, &graph, &plan, 1);
+
+        let dot = tunnels_to_dot(&tunnels, &routes);
+        assert!(dot.starts_with("graph tunnels {"));
+        assert!(dot.contains("    DD [label=\"DD\\n20\", style=filled, fillcolor=red];"));
+        assert!(dot.contains("    FF [label=\"FF\\n0\"];"));
+        assert!(dot.contains("    AA -- DD [color=red, penwidth=3];"));
+        assert!(dot.contains("    AA -- BB [color=red, penwidth=3];"));
+        assert_eq!(dot.matches(" -- ").count(), 10);
+
+        let dot = compressed_to_dot(&graph, &plan);
+        assert!(dot.starts_with("digraph valves {"));
+        assert!(dot.contains("    AA -> DD [label=1, dir=none, color=gray];"));
+        assert!(dot.contains("    AA -> DD [label=\"2\", color=red, penwidth=3];"));
+        assert!(dot.contains("    DD -> BB [label=\"5\", color=red, penwidth=3];"));
+        assert_eq!(dot.matches("dir=none").count(), 21);
+    }
+
+    #[test]
+    fn export_test() {
+        let dir = std::env::temp_dir().join(format!("day16-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let prefix = dir.join("plan");
+        export_part2(example().iter(), prefix.to_str().unwrap()).unwrap();
+        let read = |suffix: &str| {
+            std::fs::read_to_string(format!("{}{}", prefix.to_str().unwrap(), suffix)).unwrap()
+        };
+        assert!(read(".txt").starts_with("== Minute 1 ==\n"));
+        assert!(read("-tunnels.dot").starts_with("graph tunnels {"));
+        assert!(read("-valves.dot").starts_with("digraph valves {"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
 
 fn main() {
     print!("{:?}\n", part1(read_aoc_lines!()));
     print!("{:?}\n", part2(read_aoc_lines!()));
+    // Pass a path prefix to also write out the part 2 plan and the graphs.
+    if let Some(prefix) = std::env::args().nth(1) {
+        print!("{:?}\n", export_part2(read_aoc_lines!(), &prefix));
+    }
 }
@@ -63,7 +63,8 @@ fn debug_draw(chamber: &Grid<bool>) {
     }
 }
 
-fn part1<I: InputIterator>(mut input: I) -> Result<i64> {
+// Drops `num_shapes` rocks one by one and measures the tower.
+fn simulate<I: InputIterator>(mut input: I, num_shapes: usize) -> Result<i64> {
     let jet_pattern = input
         .next()
         .ok_or_err()?
@@ -81,7 +82,6 @@ fn part1<I: InputIterator>(mut input: I) -> Result<i64> {
     let rocks = rocks();
     let rock_heights = rock_heights();
     let mut max_height = -1;
-    let num_shapes = 2022;
     let mut chamber = Grid::new((num_shapes as i64) * 5, 7, false);
 
     for rock_index in 0..num_shapes {
@@ -121,7 +121,19 @@ fn part1<I: InputIterator>(mut input: I) -> Result<i64> {
 
     Ok(max_height + 1)
 }
-fn part2<I: InputIterator>(mut input: I) -> Result<i64> {
+
+fn part1<I: InputIterator>(input: I) -> Result<i64> {
+    simulate(input, 2022)
+}
+
+// Columns deeper than this below the top of the tower count as equally deep in the cycle
+// state, so that columns rocks never reach don't keep the state from repeating. This is a
+// heuristic: verification only compares these capped profiles for one more cycle, so a
+// difference further down (say a 1-wide shaft a vertical rock later drops into) can still
+// pass it. A larger limit makes that less likely but takes longer to find a cycle.
+const PROFILE_DEPTH: i64 = 32;
+
+fn part2<I: InputIterator>(mut input: I, target_shapes: usize) -> Result<i64> {
     let jet_pattern = input
         .next()
         .ok_or_err()?
@@ -135,18 +147,22 @@ fn part2<I: InputIterator>(mut input: I) -> Result<i64> {
             }
         })
         .collect::<Result<Vec<_>>>()?;
+    if target_shapes == 0 {
+        return Ok(0);
+    }
     let mut jet_index = 0;
     let rocks = rocks();
     let rock_heights = rock_heights();
     let num_shapes = 1000000;
     let mut chamber = Grid::new((num_shapes as i64) * 5, 7, false);
     let mut max_heights = vec![];
+    let mut column_tops = [-1i64; 7];
 
     // cycle detection state
     let mut cycle_detector = HashMap::new();
-    let mut cycle_start_state: Option<_> = None;
-    let mut cycle_start: usize = 0;
-    let mut cycle_end: usize = 0;
+    let mut states = vec![];
+    let mut candidate: Option<(usize, usize)> = None;
+    let mut cycle: Option<(usize, usize)> = None;
 
     for rock_index in 0..num_shapes {
         let shape = &rocks[rock_index % 5];
@@ -175,53 +191,59 @@ fn part2<I: InputIterator>(mut input: I) -> Result<i64> {
             shape_coord = fall_coord;
         }
         shape.iter().for_each(|p| {
-            chamber[shape_coord + *p] = true;
+            let c = shape_coord + *p;
+            chamber[c] = true;
+            column_tops[c.j as usize] = max(column_tops[c.j as usize], c.i);
         });
 
-        max_heights.push(max(
+        let max_height = max(
             *max_heights.last().unwrap_or(&-1),
             shape_coord.i + shape_height,
-        ));
+        );
+        max_heights.push(max_height);
+        if rock_index + 1 == target_shapes {
+            return Ok(max_height + 1);
+        }
 
+        // The surface profile (depth of each column below the top) together with the rock and
+        // jet indices; the last rock's position alone can alias distinct towers.
         let state = (
-            Coord {
-                i: max_heights.last().unwrap() - shape_coord.i,
-                j: shape_coord.j,
-            },
+            column_tops.map(|t| (max_height - t).min(PROFILE_DEPTH)),
             rock_index % 5,
             jet_index % jet_pattern.len(),
         );
-        if let Some(s) = cycle_start_state {
-            if s == state {
-                cycle_end = rock_index;
+        states.push(state);
+
+        if let Some((start, end)) = candidate {
+            // Re-simulate one extra cycle, checking every rock in it repeats the first one.
+            let offset = rock_index - end;
+            let duration = end - start;
+            let cycle_height = max_heights[end] - max_heights[start];
+            let repeats = states[start + offset] == state
+                && max_heights[start + offset] + cycle_height == max_height;
+            if !repeats {
+                candidate = None;
+            } else if offset == duration {
+                cycle = Some((start, end));
                 break;
             }
         }
-        if let Some(_i) = cycle_detector.get(&state) { // we have a match
-            cycle_start_state = match cycle_start_state {
-                None => {  // starting a new cycle
-                    cycle_start = rock_index;
-                    Some(state)
-                }
-                Some(s) => {  // continuing an existing one
-                    Some(s)
-                }
-            };
-        }
-        else {
-            cycle_start_state = None; // end any cycle if tracking
+        if candidate.is_none() {
+            // Either nothing was being verified or it just failed; this rock may start a cycle.
+            if let Some(&prev) = cycle_detector.get(&state) {
+                candidate = Some((prev, rock_index));
+            }
         }
         cycle_detector.insert(state, rock_index);
     }
 
+    let (cycle_start, cycle_end) = cycle.ok_or_else(|| InputError::new("No cycle found"))?;
     let cycle_duration = cycle_end - cycle_start;
     let cycle_height = max_heights[cycle_end] - max_heights[cycle_start];
-    let shapes_left = 1000000000000 - cycle_end;
-    let full_cycles = (shapes_left / cycle_duration) as i64;
-    let leftover = shapes_left % cycle_duration;
-    let total = max_heights[cycle_end]
-        + full_cycles * cycle_height
-        + (max_heights[cycle_start + leftover] - max_heights[cycle_start]);
+    let last_index = target_shapes - 1;
+    let full_cycles = ((last_index - cycle_start) / cycle_duration) as i64;
+    let leftover = (last_index - cycle_start) % cycle_duration;
+    let total = max_heights[cycle_start + leftover] + full_cycles * cycle_height + 1;
 
     Ok(total)
 }
@@ -230,15 +252,51 @@ fn part2<I: InputIterator>(mut input: I) -> Result<i64> {
 mod tests {
     use crate::part1;
     use crate::part2;
+    use crate::simulate;
     #[test]
     fn day17_test() {
         let input = vec![">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>"];
         assert_eq!(part1(input.iter()).unwrap(), 3068);
-        assert_eq!(part2(input.iter()).unwrap(), 1514285714288);
+        assert_eq!(part2(input.iter(), 1000000000000).unwrap(), 1514285714288);
+    }
+
+    #[test]
+    fn cycle_test() {
+        // Single-direction jets leave columns that rocks never reach.
+        let expected = [
+            (">", 2600000000000),
+            ("<", 2200000000000),
+            (">>><", 2200000000000),
+            ("<<<<>", 2100000000002),
+        ];
+        for (pattern, height) in expected {
+            assert_eq!(part2([pattern].iter(), 1000000000000).unwrap(), height);
+        }
+
+        // "<>><<<>>" has states that repeat before the tower does, which the verification
+        // pass has to reject.
+        for pattern in [
+            ">",
+            "<",
+            ">>><",
+            "<<<<>",
+            "<>><<<>>",
+            ">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>",
+        ] {
+            for target in [0, 1, 2, 5, 17, 60, 333, 1000, 2022, 3001] {
+                assert_eq!(
+                    part2([pattern].iter(), target).unwrap(),
+                    simulate([pattern].iter(), target).unwrap(),
+                    "{} rocks with {}",
+                    target,
+                    pattern
+                );
+            }
+        }
     }
 }
 
 fn main() {
     print!("{:?}\n", part1(read_aoc_lines!()));
-    print!("{:?}\n", part2(read_aoc_lines!()));
+    print!("{:?}\n", part2(read_aoc_lines!(), 1000000000000));
 }
@@ -1,37 +1,9 @@
-use std::{
-    cmp::{max, min},
-    collections::{HashSet},
-    ops::{Add, RangeInclusive},
-};
+use std::collections::HashSet;
+use std::fmt::Write;
 
-use advent_2022::{read_aoc_lines, InputIterator};
+use advent_2022::{read_aoc_lines, Grid3, InputIterator, MultiCoord};
 use anyhow::Result;
 
-#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
-pub struct MultiCoord<const N: usize> {
-    pub coords: [i64; N],
-}
-
-impl<const N: usize> Default for MultiCoord<N> {
-    fn default() -> Self {
-        Self { coords: [0i64; N] }
-    }
-}
-
-impl<const N: usize> Add<MultiCoord<N>> for MultiCoord<N> {
-    type Output = MultiCoord<N>;
-
-    fn add(self, rhs: MultiCoord<N>) -> Self::Output {
-        let mut r = MultiCoord {
-            coords: self.coords,
-        };
-        for i in 0..N {
-            r.coords[i] += rhs.coords[i];
-        }
-        r
-    }
-}
-
 fn parse_line(s: &str) -> Result<MultiCoord<3>> {
     let mut r = MultiCoord::<3>::default();
     s.split(',')
@@ -44,129 +16,308 @@ fn parse_line(s: &str) -> Result<MultiCoord<3>> {
     Ok(r)
 }
 
-fn neighbors() -> [MultiCoord<3>; 6] {
-    [
-        MultiCoord { coords: [1, 0, 0] },
-        MultiCoord { coords: [-1, 0, 0] },
-        MultiCoord { coords: [0, 1, 0] },
-        MultiCoord { coords: [0, -1, 0] },
-        MultiCoord { coords: [0, 0, 1] },
-        MultiCoord { coords: [0, 0, -1] },
-    ]
-}
-
-fn part1<I: InputIterator>(input: I) -> Result<usize> {
-    let set = input
-        .map(|x| parse_line(x.as_ref()))
-        .collect::<Result<HashSet<_>>>()?;
+const LAVA_COLOR: [u8; 3] = [207, 64, 16];
+const POCKET_COLOR: [u8; 3] = [64, 160, 255];
 
-    let neighbors = neighbors();
+// The corners of the unit square between `cube` and its neighbour `n`, counter-clockwise
+// when seen from `n`.
+fn face_corners(cube: MultiCoord<3>, n: MultiCoord<3>) -> [MultiCoord<3>; 4] {
+    let d = n - cube;
+    let axis = (0..3).find(|&k| d.coords[k] != 0).unwrap();
+    let a = MultiCoord::unit((axis + 1) % 3);
+    let b = MultiCoord::unit((axis + 2) % 3);
+    if d.coords[axis] > 0 {
+        let base = cube + d;
+        [base, base + a, base + a + b, base + b]
+    } else {
+        [cube, cube + b, cube + a + b, cube + a]
+    }
+}
 
-    Ok(set
-        .iter()
-        .map(|c| {
-            neighbors
-                .iter()
-                .filter(|n| !set.contains(&(**n + *c)))
-                .count()
-        })
-        .sum())
+// The lava cubes in a grid with a layer of air around them.
+struct Droplet {
+    lava: Grid3<bool>,
 }
 
-fn contains_point(bounds: &[RangeInclusive<i64>; 3], p: &MultiCoord<3>) -> bool {
-    (0..3).all(|i| bounds[i].contains(&p.coords[i]))
+#[derive(Debug, PartialEq)]
+struct Report {
+    surface_area: usize,
+    exterior_area: usize,
+    pocket_volumes: Vec<usize>,
 }
 
-fn steam_fill(
-    steam: &mut HashSet<MultiCoord<3>>,
-    lava: &HashSet<MultiCoord<3>>,
-    bounds: &[RangeInclusive<i64>; 3],
-    start: MultiCoord<3>,
-) {
-    let mut points = vec![start];
-    while let Some(p) = points.pop() {
-        for n in neighbors() {
-            let current = p + n;
-            if lava.contains(&current) || steam.contains(&current) {
-                continue;
-            }
-            if !contains_point(&bounds, &current) {
-                continue;
+impl Droplet {
+    fn new(cubes: &HashSet<MultiCoord<3>>) -> Droplet {
+        let (min, max) = MultiCoord::bounding_box(cubes.iter().copied()).unwrap_or_default();
+        let padding = MultiCoord::from([1; 3]);
+        let mut lava = Grid3::new(min - padding, max + padding, false);
+        for c in cubes {
+            lava[*c] = true;
+        }
+        Droplet { lava }
+    }
+
+    // The padding is never lava, so steam can enter the whole box from any face.
+    fn exterior(&self) -> Grid3<bool> {
+        self.lava.fill_from_boundary(|lava| !lava)
+    }
+
+    // The faces between a cell satisfying `solid` and a neighbour satisfying `open`, as
+    // (cell, neighbour) pairs.
+    fn exposed_faces<S, O>(&self, solid: S, open: O) -> Vec<(MultiCoord<3>, MultiCoord<3>)>
+    where
+        S: Fn(MultiCoord<3>) -> bool,
+        O: Fn(MultiCoord<3>) -> bool,
+    {
+        self.lava
+            .iter()
+            .map(|(c, _)| c)
+            .filter(|c| solid(*c))
+            .flat_map(|c| c.neighbors().filter(|n| open(*n)).map(move |n| (c, n)))
+            .collect()
+    }
+
+    // Counts the faces of lava cubes whose neighbour satisfies `open`.
+    fn faces<F: Fn(MultiCoord<3>) -> bool>(&self, open: F) -> usize {
+        self.exposed_faces(|c| self.lava[c], open).len()
+    }
+
+    fn report(&self) -> Report {
+        let exterior = self.exterior();
+        let pockets = self
+            .lava
+            .components(|lava| !lava)
+            .into_iter()
+            .filter(|c| !exterior[c[0]])
+            .map(|c| c.len())
+            .collect();
+        Report {
+            surface_area: self.faces(|n| !self.lava[n]),
+            exterior_area: self.faces(|n| exterior[n]),
+            pocket_volumes: pockets,
+        }
+    }
+
+    // An ASCII PLY mesh of the lava faces that touch air, optionally with the surfaces of
+    // the trapped air pockets in a different colour.
+    fn to_ply(&self, with_pockets: bool) -> String {
+        let mut faces: Vec<_> = self
+            .exposed_faces(|c| self.lava[c], |n| !self.lava[n])
+            .into_iter()
+            .map(|f| (f, LAVA_COLOR))
+            .collect();
+        if with_pockets {
+            let exterior = self.exterior();
+            let pocket = |c| !self.lava[c] && !exterior[c];
+            faces.extend(
+                self.exposed_faces(pocket, |n| self.lava[n])
+                    .into_iter()
+                    .map(|f| (f, POCKET_COLOR)),
+            );
+        }
+
+        let mut out = String::new();
+        writeln!(out, "ply").unwrap();
+        writeln!(out, "format ascii 1.0").unwrap();
+        writeln!(out, "element vertex {}", faces.len() * 4).unwrap();
+        for axis in ["x", "y", "z"] {
+            writeln!(out, "property int {}", axis).unwrap();
+        }
+        for channel in ["red", "green", "blue"] {
+            writeln!(out, "property uchar {}", channel).unwrap();
+        }
+        writeln!(out, "element face {}", faces.len()).unwrap();
+        writeln!(out, "property list uchar int vertex_indices").unwrap();
+        writeln!(out, "end_header").unwrap();
+        for ((cube, n), [r, g, b]) in faces.iter() {
+            for v in face_corners(*cube, *n) {
+                let [x, y, z] = v.coords;
+                writeln!(out, "{} {} {} {} {} {}", x, y, z, r, g, b).unwrap();
             }
-            steam.insert(current);
-            points.push(current);
         }
+        for i in 0..faces.len() {
+            let k = i * 4;
+            writeln!(out, "4 {} {} {} {}", k, k + 1, k + 2, k + 3).unwrap();
+        }
+        out
     }
 }
 
-fn part2<I: InputIterator>(input: I) -> Result<usize> {
-    let lava = input
+fn parse_droplet<I: InputIterator>(input: I) -> Result<Droplet> {
+    let cubes = input
         .map(|x| parse_line(x.as_ref()))
         .collect::<Result<HashSet<_>>>()?;
+    Ok(Droplet::new(&cubes))
+}
 
-    let neighbors = neighbors();
+fn analyze<I: InputIterator>(input: I) -> Result<Report> {
+    Ok(parse_droplet(input)?.report())
+}
 
-    let max = lava.iter().fold([0i64; 3], |mut acc, x| {
-        for i in 0..3 {
-            acc[i] = max(acc[i], x.coords[i]);
-        }
-        acc
-    });
-    let min = lava.iter().fold([0i64; 3], |mut acc, x| {
-        for i in 0..3 {
-            acc[i] = min(acc[i], x.coords[i]);
-        }
-        acc
-    });
-
-    let bounds = [
-        ((min[0] - 1)..=(max[0] + 1)),
-        ((min[1] - 1)..=(max[1] + 1)),
-        ((min[2] - 1)..=(max[2] + 1)),
-    ];
-
-    let start = MultiCoord {
-        coords: [0, 0, max[2] + 1],
-    };
-    let mut steam = HashSet::from([start]);
-    steam_fill(&mut steam, &lava, &bounds, start);
-
-    Ok(lava
-        .iter()
-        .map(|c| {
-            neighbors
-                .iter()
-                .filter(|n| steam.contains(&(**n + *c)))
-                .count()
-        })
-        .sum())
+fn part1<I: InputIterator>(input: I) -> Result<usize> {
+    Ok(analyze(input)?.surface_area)
+}
+
+fn part2<I: InputIterator>(input: I) -> Result<usize> {
+    Ok(analyze(input)?.exterior_area)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::contains_point;
+    use crate::analyze;
+    use crate::parse_droplet;
+    use crate::parse_line;
     use crate::part1;
     use crate::part2;
-    use crate::MultiCoord;
+    use crate::Droplet;
+    use crate::Report;
+    use advent_2022::{Grid3, MultiCoord};
     #[test]
     fn contains() {
-        let bounds = [-1..=4, -1..=4, -1..=7];
+        let bounds = Grid3::new([-1, -1, -1].into(), [4, 4, 7].into(), false);
         let p = MultiCoord { coords: [1, 0, -4] };
-        assert_eq!(contains_point(&bounds, &p), false);
+        assert_eq!(bounds.contains(p), false);
     }
 
-    #[test]
-    fn day18_test() {
-        let input = vec![
+    fn example() -> Vec<&'static str> {
+        vec![
             "2,2,2", "1,2,2", "3,2,2", "2,1,2", "2,3,2", "2,2,1", "2,2,3", "2,2,4", "2,2,6",
             "1,2,5", "3,2,5", "2,1,5", "2,3,5",
-        ];
+        ]
+    }
+
+    #[test]
+    fn day18_test() {
+        let input = example();
         assert_eq!(part1(input.iter()).unwrap(), 64);
         assert_eq!(part2(input.iter()).unwrap(), 58);
+        assert_eq!(
+            analyze(input.iter()).unwrap(),
+            Report {
+                surface_area: 64,
+                exterior_area: 58,
+                pocket_volumes: vec![1],
+            }
+        );
+    }
+
+    #[test]
+    fn far_from_origin_test() {
+        for offset in [
+            [1000000, 1000000, 1000000],
+            [-1000000, -2000000, -3000000],
+            [-10, 0, 10],
+            [0, 0, -7],
+        ] {
+            let cubes: Vec<_> = example()
+                .iter()
+                .map(|s| parse_line(s).unwrap() + offset.into())
+                .collect();
+            let input: Vec<_> = cubes
+                .iter()
+                .map(|c| format!("{},{},{}", c.coords[0], c.coords[1], c.coords[2]))
+                .collect();
+            assert_eq!(part1(input.iter()).unwrap(), 64);
+            assert_eq!(part2(input.iter()).unwrap(), 58);
+
+            let droplet = Droplet::new(&cubes.into_iter().collect());
+            let min = MultiCoord::from([0, 0, 0]) + offset.into();
+            assert_eq!(droplet.lava.min(), min);
+            assert_eq!(droplet.lava.max(), min + [4, 4, 7].into());
+        }
+
+        assert_eq!(analyze(["-5,-5,-5"].iter()).unwrap().exterior_area, 6);
+        assert_eq!(analyze(Vec::<&str>::new().iter()).unwrap().surface_area, 0);
+    }
+
+    // Parses the vertices and faces back out of a PLY mesh.
+    fn read_ply(ply: &str) -> (Vec<[i64; 6]>, Vec<[usize; 4]>) {
+        let body = ply.split_once("end_header\n").unwrap().1;
+        let (mut vertices, mut faces) = (vec![], vec![]);
+        for line in body.lines() {
+            let values: Vec<i64> = line.split(' ').map(|x| x.parse().unwrap()).collect();
+            if values.len() == 6 {
+                vertices.push(std::array::from_fn(|k| values[k]));
+            } else {
+                assert_eq!(values[0], 4);
+                faces.push(std::array::from_fn(|k| values[k + 1] as usize));
+            }
+        }
+        (vertices, faces)
+    }
+
+    #[test]
+    fn ply_test() {
+        let ply = parse_droplet(["3,-2,7"].iter()).unwrap().to_ply(true);
+        assert!(ply.starts_with("ply\nformat ascii 1.0\nelement vertex 24\n"));
+        assert!(ply.contains("element face 6\n"));
+        let (vertices, faces) = read_ply(&ply);
+        assert_eq!((vertices.len(), faces.len()), (24, 6));
+        assert!(vertices
+            .iter()
+            .all(|v| [3, 4].contains(&v[0]) && [-2, -1].contains(&v[1]) && [7, 8].contains(&v[2])));
+        for face in faces {
+            // Doubled coordinates keep the centres integral.
+            let p: Vec<[i64; 3]> = face
+                .iter()
+                .map(|&i| std::array::from_fn(|k| vertices[i][k] * 2))
+                .collect();
+            let e1: [i64; 3] = std::array::from_fn(|k| p[1][k] - p[0][k]);
+            let e2: [i64; 3] = std::array::from_fn(|k| p[2][k] - p[1][k]);
+            let normal = [
+                e1[1] * e2[2] - e1[2] * e2[1],
+                e1[2] * e2[0] - e1[0] * e2[2],
+                e1[0] * e2[1] - e1[1] * e2[0],
+            ];
+            let outward: [i64; 3] =
+                std::array::from_fn(|k| (p[0][k] + p[2][k]) / 2 - [7, -3, 15][k]);
+            assert!((0..3).map(|k| normal[k] * outward[k]).sum::<i64>() > 0);
+        }
+
+        let droplet = parse_droplet(example().iter()).unwrap();
+        let (vertices, faces) = read_ply(&droplet.to_ply(false));
+        assert_eq!((vertices.len(), faces.len()), (64 * 4, 64));
+        let (vertices, faces) = read_ply(&droplet.to_ply(true));
+        assert_eq!(faces.len(), 64 + 6);
+        let pocket_vertices = vertices.iter().filter(|v| v[3..] == [64, 160, 255]);
+        assert!(pocket_vertices
+            .clone()
+            .all(|v| [2, 3].contains(&v[0]) && [2, 3].contains(&v[1]) && [5, 6].contains(&v[2])));
+        assert_eq!(pocket_vertices.count(), 6 * 4);
+    }
+
+    #[test]
+    fn pockets_test() {
+        // A 3x3x4 block around a 1x1x2 pocket, and a separate 1x1x2 bar.
+        let mut input = vec![];
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..4 {
+                    if (x, y) != (1, 1) || z == 0 || z == 3 {
+                        input.push(format!("{},{},{}", x, y, z));
+                    }
+                }
+            }
+        }
+        input.extend(["5,5,5".to_string(), "5,5,6".to_string()]);
+        let report = analyze(input.iter()).unwrap();
+        assert_eq!(report.pocket_volumes, [2]);
+        assert_eq!(report.exterior_area, 2 * 9 + 4 * 12 + 10);
+        assert_eq!(report.surface_area, report.exterior_area + 10);
     }
 }
 
 fn main() {
     print!("{:?}\n", part1(read_aoc_lines!()));
     print!("{:?}\n", part2(read_aoc_lines!()));
+    print!(
+        "{:?}\n",
+        analyze(read_aoc_lines!()).map(|r| r.pocket_volumes)
+    );
+    // Pass a path to also write the droplet and its air pockets there as a PLY mesh.
+    if let Some(path) = std::env::args().nth(1) {
+        let written = parse_droplet(read_aoc_lines!())
+            .and_then(|d| Ok(std::fs::write(&path, d.to_ply(true))?));
+        print!("{:?}\n", written);
+    }
 }
@@ -1,28 +1,81 @@
 use std::cmp::max;
-use std::thread;
+use std::collections::HashMap;
 
-use advent_2022::{read_aoc_lines, InputIterator, OptionUtils};
+use advent_2022::{check, read_aoc_lines, InputError, InputIterator, WorkQueue};
 use anyhow::Result;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resource {
+    Ore,
+    Clay,
+    Obsidian,
+    Geode,
+}
+
+impl Resource {
+    const ALL: [Resource; 4] = [
+        Resource::Ore,
+        Resource::Clay,
+        Resource::Obsidian,
+        Resource::Geode,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Resource::Ore => "ore",
+            Resource::Clay => "clay",
+            Resource::Obsidian => "obsidian",
+            Resource::Geode => "geode",
+        }
+    }
+
+    fn robot_name(&self) -> &'static str {
+        match self {
+            Resource::Ore => "ore-collecting robot",
+            Resource::Clay => "clay-collecting robot",
+            Resource::Obsidian => "obsidian-collecting robot",
+            Resource::Geode => "geode-cracking robot",
+        }
+    }
+
+    fn from_name(name: &str) -> Result<Resource> {
+        Resource::ALL
+            .into_iter()
+            .find(|r| r.name() == name)
+            .ok_or_else(|| InputError::new(format!("Unknown resource {}", name)).into())
+    }
+}
+
+// Amounts per resource, indexed by `Resource as usize`.
+type Resources = [i64; 4];
+
 struct BluePrint {
-    ore_cost: i64,
-    clay_cost: i64,
-    obsidian_cost: (i64, i64),
-    geode_cost: (i64, i64),
+    // The cost of each robot type, indexed by the resource it collects.
+    costs: [Resources; 4],
 }
 
-#[derive(Debug, Default, Clone, Copy)]
-struct State {
-    ore: i64,
-    clay: i64,
-    obsidian: i64,
+impl BluePrint {
+    fn cost(&self, robot: Resource) -> Resources {
+        self.costs[robot as usize]
+    }
 
-    ore_bots: i64,
-    clay_bots: i64,
-    obsidian_bots: i64,
+    // The most of each resource that can be spent in a minute; geodes are never capped.
+    fn max_spend(&self) -> Resources {
+        let mut result = [0, 0, 0, i64::MAX];
+        for cost in self.costs.iter() {
+            for r in 0..3 {
+                result[r] = max(result[r], cost[r]);
+            }
+        }
+        result
+    }
+}
 
+#[derive(Debug, Default, Clone, Copy)]
+struct State {
+    resources: Resources,
+    robots: Resources,
     minutes_left: i64,
-    geodes_opened: i64,
 }
 
 fn delay(resource: i64, num_bots: i64) -> i64 {
@@ -33,212 +86,695 @@ impl State {
     fn new(num_minutes: i64) -> State {
         State {
             minutes_left: num_minutes,
-            ore_bots: 1,
+            robots: [1, 0, 0, 0],
             ..Default::default()
         }
     }
 
-    fn collect(&mut self, minutes: i64) {
-        self.ore += minutes * self.ore_bots;
-        self.clay += minutes * self.clay_bots;
-        self.obsidian += minutes * self.obsidian_bots;
+    fn geodes_opened(&self) -> i64 {
+        let geode = Resource::Geode as usize;
+        self.resources[geode] + self.robots[geode] * self.minutes_left
     }
 
-    fn build_ore_bot(&self, blueprint: &BluePrint) -> Option<State> {
-        let missing_ore = max(blueprint.ore_cost - self.ore, 0);
-        let minutes_delay = delay(missing_ore, self.ore_bots);
-        let minutes_left = self.minutes_left - minutes_delay;
-        if minutes_left <= 1 {
-            return None;
+    // Waits until the robot is affordable and builds it, if that leaves it time to be useful.
+    fn build(&self, blueprint: &BluePrint, robot: Resource) -> Option<State> {
+        let mut minutes_delay = 1;
+        for (r, cost) in blueprint.cost(robot).iter().enumerate() {
+            let missing = max(cost - self.resources[r], 0);
+            if missing > 0 {
+                if self.robots[r] == 0 {
+                    return None;
+                }
+                minutes_delay = max(minutes_delay, delay(missing, self.robots[r]));
+            }
         }
-        let mut s = State {
-            minutes_left,
-            ..*self
-        };
-        s.collect(minutes_delay);
-        s.ore_bots += 1;
-        s.ore -= blueprint.ore_cost;
-        Some(s)
-    }
-    fn build_clay_bot(&self, blueprint: &BluePrint) -> Option<State> {
-        let missing_ore = max(blueprint.clay_cost - self.ore, 0);
-        let minutes_delay = delay(missing_ore, self.ore_bots);
         let minutes_left = self.minutes_left - minutes_delay;
-        if minutes_left <= 1 {
+        let useful_after = if robot == Resource::Geode { 0 } else { 1 };
+        if minutes_left <= useful_after {
             return None;
         }
         let mut s = State {
             minutes_left,
             ..*self
         };
-        s.collect(minutes_delay);
-        s.clay_bots += 1;
-        s.ore -= blueprint.clay_cost;
-        Some(s)
-    }
-    fn build_obsidian_bot(&self, blueprint: &BluePrint) -> Option<State> {
-        if self.clay_bots == 0 {
-            return None;
-        }
-        let missing_ore = max(blueprint.obsidian_cost.0 - self.ore, 0);
-        let missing_clay = max(blueprint.obsidian_cost.1 - self.clay, 0);
-        let minutes_delay = max(
-            delay(missing_ore, self.ore_bots),
-            delay(missing_clay, self.clay_bots),
-        );
-        let minutes_left = self.minutes_left - minutes_delay;
-        if minutes_left <= 1 {
-            return None;
+        for (r, cost) in blueprint.cost(robot).iter().enumerate() {
+            s.resources[r] += minutes_delay * self.robots[r] - cost;
         }
-        let mut s = State {
-            minutes_left,
-            ..*self
-        };
-        s.collect(minutes_delay);
-        s.obsidian_bots += 1;
-        s.ore -= blueprint.obsidian_cost.0;
-        s.clay -= blueprint.obsidian_cost.1;
+        s.robots[robot as usize] += 1;
         Some(s)
     }
-    fn build_geode_bot(&self, blueprint: &BluePrint) -> Option<State> {
-        if self.obsidian_bots == 0 {
-            return None;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Visited {
+    Ignore,
+    Exact,
+    Dominance,
+}
+
+// Which pruning rules `optimize_blueprint_with` applies, so their effect can be compared.
+#[derive(Debug, Clone, Copy)]
+struct Pruning {
+    resource_caps: bool,
+    visited: Visited,
+    tight_bound: bool,
+}
+
+impl Default for Pruning {
+    fn default() -> Pruning {
+        Pruning {
+            resource_caps: true,
+            visited: Visited::Dominance,
+            tight_bound: true,
         }
-        let missing_ore = max(blueprint.geode_cost.0 - self.ore, 0);
-        let missing_obsidian = max(blueprint.geode_cost.1 - self.obsidian, 0);
-        let minutes_delay = max(
-            delay(missing_ore, self.ore_bots),
-            delay(missing_obsidian, self.obsidian_bots),
-        );
-        let minutes_left = self.minutes_left - minutes_delay;
-        if minutes_left <= 0 {
-            return None;
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct SearchStats {
+    explored: u64,
+    pruned_by_bound: u64,
+    pruned_by_visited: u64,
+}
+
+// Upper bound on geodes, simulating every robot type being built in the same minute whenever
+// affordable. With `separate_pools` each type pays from its own copy of the stockpile (which
+// every robot still collects into), otherwise costs aren't paid at all.
+fn geode_bound(s: &State, blueprint: &BluePrint, separate_pools: bool) -> i64 {
+    let mut pools = [s.resources; 4];
+    let mut robots = s.robots;
+    let mut geodes = s.resources[Resource::Geode as usize];
+    for _ in 0..s.minutes_left {
+        // Robots finished this minute only start collecting in the next one.
+        let collecting = robots;
+        geodes += collecting[Resource::Geode as usize];
+        for (robot, pool) in pools.iter_mut().enumerate() {
+            let cost = blueprint.costs[robot];
+            let affordable = pool.iter().zip(cost).all(|(p, c)| *p >= c);
+            for (p, n) in pool.iter_mut().zip(collecting) {
+                *p += n;
+            }
+            if affordable {
+                robots[robot] += 1;
+                if separate_pools {
+                    for (p, c) in pool.iter_mut().zip(cost) {
+                        *p -= c;
+                    }
+                }
+            }
         }
-        let mut s = State {
-            minutes_left,
-            ..*self
-        };
-        s.collect(minutes_delay);
-        s.ore -= blueprint.geode_cost.0;
-        s.obsidian -= blueprint.geode_cost.1;
-        s.geodes_opened += minutes_left;
-        Some(s)
     }
+    geodes
 }
 
-fn optimize_blueprint(blueprint: &BluePrint, num_minutes: i64) -> i64 {
-    let mut v = vec![State::new(num_minutes)];
-    let mut max_geodes_opened = 0;
+// Resources beyond what can possibly be spent in the remaining time don't change the outcome, so
+// they're clamped to make more states compare equal (or dominated).
+fn capped_resources(s: &State, max_spend: &Resources) -> Resources {
+    let mut result = s.resources;
+    for r in 0..3 {
+        result[r] = result[r].min(max_spend[r] * s.minutes_left);
+    }
+    result
+}
 
-    while let Some(s) = v.pop() {
-        max_geodes_opened = max(max_geodes_opened, s.geodes_opened);
+// Keyed by robots and time left; holds the resources of states already seen.
+type VisitedTable = HashMap<(Resources, i64), Vec<Resources>>;
 
-        let max_clay =
-            s.clay + (s.clay_bots * s.minutes_left) + s.minutes_left * (s.minutes_left - 1) / 2;
-        let max_built_obs_bots = max_clay / blueprint.obsidian_cost.1;
-        let max_obs =
-            s.obsidian + (s.obsidian_bots + (max_built_obs_bots + 1) / 2) * s.minutes_left;
-        let max_built_geode_bots = max_obs / blueprint.geode_cost.1;
+struct Solution {
+    geodes_opened: i64,
+    // The minute each robot was started in, in order.
+    builds: Vec<(i64, Resource)>,
+    stats: SearchStats,
+}
 
-        if max_built_geode_bots == 0 {
-            continue;
+fn optimize_blueprint_with(blueprint: &BluePrint, num_minutes: i64, pruning: Pruning) -> Solution {
+    // Each state carries an index into `history`, whose entries link back to the build before.
+    let mut v = vec![(State::new(num_minutes), None)];
+    let mut history: Vec<(Option<usize>, i64, Resource)> = vec![];
+    let mut max_geodes_opened = 0;
+    let mut best = None;
+    let mut stats = SearchStats::default();
+    let max_spend = blueprint.max_spend();
+    let mut visited: VisitedTable = HashMap::new();
+
+    while let Some((s, last_build)) = v.pop() {
+        stats.explored += 1;
+        if s.geodes_opened() > max_geodes_opened {
+            max_geodes_opened = s.geodes_opened();
+            best = last_build;
         }
-        if (max_built_geode_bots * s.minutes_left + s.geodes_opened) <= max_geodes_opened {
+
+        if geode_bound(&s, blueprint, pruning.tight_bound) <= max_geodes_opened {
+            stats.pruned_by_bound += 1;
             continue;
         }
 
+        if pruning.visited != Visited::Ignore {
+            let resources = if pruning.resource_caps {
+                capped_resources(&s, &max_spend)
+            } else {
+                s.resources
+            };
+            let seen = visited.entry((s.robots, s.minutes_left)).or_default();
+            let dominated = match pruning.visited {
+                Visited::Exact => seen.contains(&resources),
+                _ => seen
+                    .iter()
+                    .any(|o| o.iter().zip(resources).all(|(a, b)| *a >= b)),
+            };
+            if dominated {
+                stats.pruned_by_visited += 1;
+                continue;
+            }
+            seen.push(resources);
+        }
 
-        if let Some(built_ore) = s.build_ore_bot(blueprint) {
-            v.push(built_ore);
+        for robot in Resource::ALL {
+            if pruning.resource_caps && s.robots[robot as usize] >= max_spend[robot as usize] {
+                continue;
+            }
+            if let Some(built) = s.build(blueprint, robot) {
+                history.push((last_build, num_minutes - built.minutes_left, robot));
+                v.push((built, Some(history.len() - 1)));
+            }
         }
-        if let Some(built_clay) = s.build_clay_bot(blueprint) {
-            v.push(built_clay);
+    }
+
+    let mut builds = vec![];
+    while let Some(i) = best {
+        builds.push((history[i].1, history[i].2));
+        best = history[i].0;
+    }
+    builds.reverse();
+    Solution {
+        geodes_opened: max_geodes_opened,
+        builds,
+        stats,
+    }
+}
+
+fn optimize_blueprint(blueprint: &BluePrint, num_minutes: i64) -> i64 {
+    optimize_blueprint_with(blueprint, num_minutes, Pruning::default()).geodes_opened
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BuildStep {
+    minute: i64,
+    robot: Resource,
+    // Ore, clay, obsidian and open geodes at the end of the minute.
+    resources_after: [i64; 4],
+}
+
+// Replays a build order minute by minute, calling `f` with the minute, the robot started in it
+// (if any), and the robots and resources at the end of the minute.
+fn replay_builds<F: FnMut(i64, Option<Resource>, &[i64; 4], &[i64; 4])>(
+    blueprint: &BluePrint,
+    num_minutes: i64,
+    builds: &[(i64, Resource)],
+    mut f: F,
+) {
+    let mut robots = [1, 0, 0, 0];
+    let mut resources = [0; 4];
+    let mut builds = builds.iter().peekable();
+    for minute in 1..=num_minutes {
+        let robot = builds.next_if(|b| b.0 == minute).map(|b| b.1);
+        if let Some(robot) = robot {
+            for (r, c) in resources.iter_mut().zip(blueprint.cost(robot)) {
+                *r -= c;
+            }
         }
-        if let Some(built_obsidian) = s.build_obsidian_bot(blueprint) {
-            v.push(built_obsidian);
+        for (r, n) in resources.iter_mut().zip(robots) {
+            *r += n;
         }
-        if let Some(built_geode) = s.build_geode_bot(blueprint) {
-            v.push(built_geode);
+        if let Some(robot) = robot {
+            robots[robot as usize] += 1;
         }
+        f(minute, robot, &robots, &resources);
     }
-    max_geodes_opened
 }
 
-fn parse_line(s: &str) -> Result<BluePrint> {
-    let mut split = s.split(' ');
-    let ore_cost = split.nth(6).ok_or_err()?.parse()?;
-    let clay_cost = split.nth(5).ok_or_err()?.parse()?;
-    let obsidian_cost = (
-        split.nth(5).ok_or_err()?.parse()?,
-        split.nth(2).ok_or_err()?.parse()?,
+// The winning build order for a blueprint, with the resources held after each build.
+#[allow(dead_code)]
+fn build_order(blueprint: &BluePrint, num_minutes: i64) -> Vec<BuildStep> {
+    let solution = optimize_blueprint_with(blueprint, num_minutes, Pruning::default());
+    let mut result = vec![];
+    replay_builds(
+        blueprint,
+        num_minutes,
+        &solution.builds,
+        |minute, robot, _, resources| {
+            if let Some(robot) = robot {
+                result.push(BuildStep {
+                    minute,
+                    robot,
+                    resources_after: *resources,
+                });
+            }
+        },
     );
-    let geode_cost = (
-        split.nth(5).ok_or_err()?.parse()?,
-        split.nth(2).ok_or_err()?.parse()?,
+    result
+}
+
+fn plural(n: i64, singular: &str, plural: &str) -> String {
+    format!("{} {}", n, if n == 1 { singular } else { plural })
+}
+
+// Reproduces the puzzle's minute-by-minute description of a build order.
+#[allow(dead_code)]
+fn narrate(blueprint: &BluePrint, num_minutes: i64, builds: &[BuildStep]) -> Vec<String> {
+    let builds: Vec<_> = builds.iter().map(|b| (b.minute, b.robot)).collect();
+    let mut result = vec![];
+    replay_builds(
+        blueprint,
+        num_minutes,
+        &builds,
+        |minute, robot, robots, resources| {
+            result.push(format!("== Minute {} ==", minute));
+            if let Some(robot) = robot {
+                let spent: Vec<_> = Resource::ALL
+                    .iter()
+                    .zip(blueprint.cost(robot))
+                    .filter(|(_, c)| *c > 0)
+                    .map(|(r, c)| format!("{} {}", c, r.name()))
+                    .collect();
+                let article = if robot == Resource::Ore || robot == Resource::Obsidian {
+                    "an"
+                } else {
+                    "a"
+                };
+                result.push(format!(
+                    "Spend {} to start building {} {}.",
+                    spent.join(" and "),
+                    article,
+                    robot.robot_name()
+                ));
+            }
+            for r in Resource::ALL {
+                // Robots finished this minute haven't collected anything yet.
+                let n = robots[r as usize] - (robot == Some(r)) as i64;
+                if n == 0 {
+                    continue;
+                }
+                let robots = plural(n, r.robot_name(), &format!("{}s", r.robot_name()));
+                result.push(if r == Resource::Geode {
+                    format!(
+                        "{} {} {}; you now have {}.",
+                        robots,
+                        if n == 1 { "cracks" } else { "crack" },
+                        plural(n, "geode", "geodes"),
+                        plural(resources[r as usize], "open geode", "open geodes")
+                    )
+                } else {
+                    format!(
+                        "{} {} {} {}; you now have {} {}.",
+                        robots,
+                        if n == 1 { "collects" } else { "collect" },
+                        n,
+                        r.name(),
+                        resources[r as usize],
+                        r.name()
+                    )
+                });
+            }
+            if let Some(robot) = robot {
+                result.push(format!(
+                    "The new {} is ready; you now have {} of them.",
+                    robot.robot_name(),
+                    robots[robot as usize]
+                ));
+            }
+            result.push(String::new());
+        },
     );
+    result
+}
 
-    Ok(BluePrint {
-        ore_cost,
-        clay_cost,
-        obsidian_cost,
-        geode_cost,
-    })
+// Progressively stronger pruning, from the bare search up to the default.
+fn pruning_strategies() -> Vec<Pruning> {
+    let bare = Pruning {
+        resource_caps: false,
+        visited: Visited::Ignore,
+        tight_bound: false,
+    };
+    let capped = Pruning {
+        resource_caps: true,
+        ..bare
+    };
+    vec![
+        bare,
+        capped,
+        Pruning {
+            visited: Visited::Exact,
+            ..capped
+        },
+        Pruning {
+            visited: Visited::Dominance,
+            ..capped
+        },
+        Pruning::default(),
+    ]
 }
 
-fn part1<I: InputIterator>(input: I) -> Result<usize> {
-    let blueprints = input
-        .map(|l| parse_line(l.as_ref()))
-        .collect::<Result<Vec<_>>>()?;
-    let results: Vec<_> = thread::scope(|s| {
-        let threads: Vec<_> = blueprints
-            .iter()
-            .map(|b| s.spawn(|| optimize_blueprint(b, 24)))
-            .collect();
-        threads.into_iter().map(|x| x.join().unwrap()).collect()
-    });
-    Ok(results
+// One line per pruning strategy with the result and how much of the search it took.
+#[allow(dead_code)]
+fn pruning_report(blueprint: &BluePrint, num_minutes: i64) -> Vec<String> {
+    pruning_strategies()
         .iter()
-        .enumerate()
-        .map(|(i, r)| (i + 1) * (*r as usize))
+        .map(|pruning| {
+            let solution = optimize_blueprint_with(blueprint, num_minutes, *pruning);
+            let stats = solution.stats;
+            format!(
+                "{:?}: {} geodes, {} states explored, {} pruned by bound, {} pruned as visited",
+                pruning,
+                solution.geodes_opened,
+                stats.explored,
+                stats.pruned_by_bound,
+                stats.pruned_by_visited
+            )
+        })
+        .collect()
+}
+
+// Parses "4 ore" or "3 ore and 14 clay" into a cost per resource.
+fn parse_cost(s: &str) -> Result<Resources> {
+    let mut cost = [0; 4];
+    for item in s.split(" and ") {
+        let (amount, name) = item
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| InputError::new(format!("Bad cost {}", item)))?;
+        cost[Resource::from_name(name.trim())? as usize] += amount.parse::<i64>()?;
+    }
+    Ok(cost)
+}
+
+// Parses the text after "Blueprint ", e.g. "1: Each ore robot costs 4 ore. Each clay ...".
+fn parse_blueprint(s: &str) -> Result<(i64, [Resources; 4])> {
+    let (id, rest) = s
+        .split_once(':')
+        .ok_or_else(|| InputError::new(format!("Missing ':' in blueprint {}", s)))?;
+    let mut costs = [None; 4];
+    for sentence in rest.split('.').map(str::trim).filter(|x| !x.is_empty()) {
+        let (robot, cost) = sentence
+            .strip_prefix("Each ")
+            .and_then(|x| x.split_once(" robot costs "))
+            .ok_or_else(|| InputError::new(format!("Unrecognized sentence {}", sentence)))?;
+        let robot = Resource::from_name(robot)?;
+        check(costs[robot as usize].is_none(), || {
+            InputError::new(format!(
+                "Duplicate {} robot in blueprint {}",
+                robot.name(),
+                id
+            ))
+        })?;
+        costs[robot as usize] = Some(parse_cost(cost)?);
+    }
+    let mut result = [[0; 4]; 4];
+    for (r, c) in Resource::ALL.iter().zip(costs) {
+        result[*r as usize] =
+            c.ok_or_else(|| InputError::new(format!("No {} robot in blueprint {}", r.name(), id)))?;
+    }
+    Ok((id.trim().parse()?, result))
+}
+
+// Blueprints may be given one per line or wrapped over several lines, so the input is joined and
+// split on "Blueprint " instead of on line breaks.
+fn parse_blueprints<I: InputIterator>(input: I) -> Result<Vec<(i64, BluePrint)>> {
+    let text = input
+        .map(|l| l.as_ref().trim().to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    text.split("Blueprint ")
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|b| {
+            let (id, costs) = parse_blueprint(b)?;
+            Ok((id, BluePrint { costs }))
+        })
+        .collect()
+}
+
+fn part1<I: InputIterator>(input: I) -> Result<i64> {
+    let blueprints = parse_blueprints(input)?;
+    let results = WorkQueue::new().map(&blueprints, |(_, b)| optimize_blueprint(b, 24));
+    Ok(blueprints
+        .iter()
+        .zip(results)
+        .map(|((id, _), r)| id * r)
         .sum())
 }
 fn part2<I: InputIterator>(input: I) -> Result<i64> {
-    let blueprints = input
-        .map(|l| parse_line(l.as_ref()))
-        .collect::<Result<Vec<_>>>()?;
-    let results: Vec<_> = thread::scope(|s| {
-        let threads: Vec<_> = blueprints[0..3]
-            .iter()
-            .map(|b| s.spawn(|| optimize_blueprint(b, 32)))
-            .collect();
-        threads.into_iter().map(|x| x.join().unwrap()).collect()
-    });
-    Ok(results.iter().fold(1, |acc, x| acc * x))
+    let blueprints = parse_blueprints(input)?;
+    let first = &blueprints[..blueprints.len().min(3)];
+    let results = WorkQueue::new().map(first, |(_, b)| optimize_blueprint(b, 32));
+    Ok(results.iter().product())
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cmp::max;
+    use std::collections::HashMap;
+
+    use crate::build_order;
+    use crate::narrate;
     use crate::optimize_blueprint;
+    use crate::optimize_blueprint_with;
+    use crate::parse_blueprints;
+    use crate::part1;
+    use crate::pruning_report;
+    use crate::pruning_strategies;
     use crate::BluePrint;
+    use crate::BuildStep;
+    use crate::Resource;
+    use crate::Resources;
     #[test]
     fn test_blueprint() {
         let blueprint1 = BluePrint {
-            ore_cost: 4,
-            clay_cost: 2,
-            obsidian_cost: (3, 14),
-            geode_cost: (2, 7),
+            costs: [[4, 0, 0, 0], [2, 0, 0, 0], [3, 14, 0, 0], [2, 0, 7, 0]],
         };
         assert_eq!(optimize_blueprint(&blueprint1, 24), 9);
         assert_eq!(optimize_blueprint(&blueprint1, 32), 56);
 
         let blueprint2 = BluePrint {
-            ore_cost: 2,
-            clay_cost: 3,
-            obsidian_cost: (3, 8),
-            geode_cost: (3, 12),
+            costs: [[2, 0, 0, 0], [3, 0, 0, 0], [3, 8, 0, 0], [3, 0, 12, 0]],
         };
         assert_eq!(optimize_blueprint(&blueprint2, 24), 12);
     }
+
+    // Minute-by-minute search over every choice, memoized on the full state.
+    fn brute_force(blueprint: &BluePrint, num_minutes: i64) -> i64 {
+        fn search(
+            blueprint: &BluePrint,
+            resources: Resources,
+            robots: Resources,
+            minutes_left: i64,
+            memo: &mut HashMap<(Resources, Resources, i64), i64>,
+        ) -> i64 {
+            if minutes_left == 0 {
+                return resources[Resource::Geode as usize];
+            }
+            if let Some(r) = memo.get(&(resources, robots, minutes_left)) {
+                return *r;
+            }
+            let mut best = 0;
+            for robot in [None, Some(0), Some(1), Some(2), Some(3)] {
+                let mut next_resources = resources;
+                let mut next_robots = robots;
+                if let Some(robot) = robot {
+                    let cost: Resources = blueprint.costs[robot];
+                    if resources.iter().zip(cost).any(|(r, c)| *r < c) {
+                        continue;
+                    }
+                    for (r, c) in next_resources.iter_mut().zip(cost) {
+                        *r -= c;
+                    }
+                    next_robots[robot] += 1;
+                }
+                for (r, n) in next_resources.iter_mut().zip(robots) {
+                    *r += n;
+                }
+                best = max(
+                    best,
+                    search(
+                        blueprint,
+                        next_resources,
+                        next_robots,
+                        minutes_left - 1,
+                        memo,
+                    ),
+                );
+            }
+            memo.insert((resources, robots, minutes_left), best);
+            best
+        }
+        search(
+            blueprint,
+            [0; 4],
+            [1, 0, 0, 0],
+            num_minutes,
+            &mut HashMap::new(),
+        )
+    }
+
+    #[test]
+    fn test_generic_costs() {
+        let blueprints = [
+            // Geode robots cost clay only, so obsidian is never needed.
+            [[2, 0, 0, 0], [2, 0, 0, 0], [3, 4, 0, 0], [0, 5, 0, 0]],
+            // Geode robots cost both clay and obsidian.
+            [[2, 0, 0, 0], [2, 0, 0, 0], [2, 3, 0, 0], [0, 2, 2, 0]],
+            // Ore robots need clay.
+            [[1, 2, 0, 0], [1, 0, 0, 0], [2, 2, 0, 0], [2, 0, 2, 0]],
+            // Geode robots cost geodes, so none can ever be built.
+            [[2, 0, 0, 0], [2, 0, 0, 0], [3, 4, 0, 0], [1, 0, 1, 1]],
+        ];
+        for costs in blueprints {
+            let blueprint = BluePrint { costs };
+            let expected = brute_force(&blueprint, 12);
+            for pruning in pruning_strategies() {
+                let solution = optimize_blueprint_with(&blueprint, 12, pruning);
+                assert_eq!(solution.geodes_opened, expected, "{:?}", costs);
+            }
+            let builds = build_order(&blueprint, 12);
+            assert!(builds
+                .iter()
+                .all(|b| b.resources_after.iter().all(|r| *r >= 0)));
+        }
+        let blueprint = BluePrint {
+            costs: blueprints[3],
+        };
+        assert_eq!(optimize_blueprint(&blueprint, 24), 0);
+    }
+
+    #[test]
+    fn test_parse() {
+        let single_line = vec![
+            "Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian.",
+            "Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsidian robot costs 3 ore and 8 clay. Each geode robot costs 3 ore and 12 obsidian.",
+        ];
+        let multi_line = vec![
+            "Blueprint 1:",
+            "  Each ore robot costs 4 ore.",
+            "  Each clay robot costs 2 ore.",
+            "  Each obsidian robot costs 3 ore and 14 clay.",
+            "  Each geode robot costs 2 ore and 7 obsidian.",
+            "",
+            "Blueprint 2:",
+            "  Each ore robot costs 2 ore.",
+            "  Each clay robot costs 3 ore.",
+            "  Each obsidian robot costs 3 ore and 8 clay.",
+            "  Each geode robot costs 3 ore and 12 obsidian.",
+        ];
+        for input in [single_line, multi_line] {
+            let blueprints = parse_blueprints(input.iter()).unwrap();
+            assert_eq!(blueprints.len(), 2);
+            assert_eq!(blueprints[1].0, 2);
+            assert_eq!(blueprints[1].1.costs[2], [3, 8, 0, 0]);
+            assert_eq!(blueprints[1].1.costs[3], [3, 0, 12, 0]);
+            assert_eq!(part1(input.iter()).unwrap(), 33);
+        }
+
+        let reordered = ["Blueprint 7: Each clay robot costs 2 ore. Each geode robot costs 7 obsidian and 2 ore. Each ore robot costs 4 ore. Each obsidian robot costs 14 clay and 3 ore."];
+        let blueprints = parse_blueprints(reordered.iter()).unwrap();
+        assert_eq!(blueprints[0].0, 7);
+        assert_eq!(blueprints[0].1.costs[3], [2, 0, 7, 0]);
+
+        for bad in [
+            "Blueprint 1: Each ore robot costs 4 ore.",
+            "Blueprint 1: Each ore robot costs 4 ore. Each ore robot costs 4 ore.",
+            "Blueprint 1: Each ore robot costs 4 gold. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian.",
+            "Blueprint 1: Each ore robot is free. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian.",
+        ] {
+            assert!(parse_blueprints([bad].iter()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_build_order() {
+        let blueprint = BluePrint {
+            costs: [[4, 0, 0, 0], [2, 0, 0, 0], [3, 14, 0, 0], [2, 0, 7, 0]],
+        };
+        let builds = build_order(&blueprint, 24);
+        assert_eq!(builds.last().unwrap().robot, Resource::Geode);
+        let geode_robots: Vec<_> = builds
+            .iter()
+            .filter(|b| b.robot == Resource::Geode)
+            .map(|b| b.minute)
+            .collect();
+        assert_eq!(geode_robots.iter().map(|m| 24 - m).sum::<i64>(), 9);
+        assert!(builds
+            .iter()
+            .all(|b| b.resources_after.iter().all(|r| *r >= 0)));
+
+        // The build order described in the puzzle.
+        let builds: Vec<_> = [
+            (3, Resource::Clay),
+            (5, Resource::Clay),
+            (7, Resource::Clay),
+            (11, Resource::Obsidian),
+            (12, Resource::Clay),
+            (15, Resource::Obsidian),
+            (18, Resource::Geode),
+            (21, Resource::Geode),
+        ]
+        .into_iter()
+        .map(|(minute, robot)| BuildStep {
+            minute,
+            robot,
+            resources_after: [0; 4],
+        })
+        .collect();
+        let lines = narrate(&blueprint, 24, &builds);
+        let minute = |m: usize| {
+            let start = lines
+                .iter()
+                .position(|l| *l == format!("== Minute {} ==", m));
+            let start = start.unwrap() + 1;
+            let end = start + lines[start..].iter().position(|l| l.is_empty()).unwrap();
+            lines[start..end].to_vec()
+        };
+        assert_eq!(
+            minute(1),
+            ["1 ore-collecting robot collects 1 ore; you now have 1 ore."]
+        );
+        assert_eq!(
+            minute(11),
+            [
+                "Spend 3 ore and 14 clay to start building an obsidian-collecting robot.",
+                "1 ore-collecting robot collects 1 ore; you now have 2 ore.",
+                "3 clay-collecting robots collect 3 clay; you now have 4 clay.",
+                "The new obsidian-collecting robot is ready; you now have 1 of them.",
+            ]
+        );
+        assert_eq!(
+            minute(24),
+            [
+                "1 ore-collecting robot collects 1 ore; you now have 6 ore.",
+                "4 clay-collecting robots collect 4 clay; you now have 41 clay.",
+                "2 obsidian-collecting robots collect 2 obsidian; you now have 8 obsidian.",
+                "2 geode-cracking robots crack 2 geodes; you now have 9 open geodes.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pruning() {
+        let blueprint = BluePrint {
+            costs: [[4, 0, 0, 0], [2, 0, 0, 0], [3, 14, 0, 0], [2, 0, 7, 0]],
+        };
+        let explored: Vec<_> = pruning_strategies()
+            .iter()
+            .map(|p| {
+                let solution = optimize_blueprint_with(&blueprint, 24, *p);
+                assert_eq!(solution.geodes_opened, 9);
+                solution.stats.explored
+            })
+            .collect();
+        assert!(explored.windows(2).all(|w| w[1] <= w[0]));
+        assert!(pruning_report(&blueprint, 24)
+            .iter()
+            .all(|l| l.contains(": 9 geodes, ")));
+    }
 }
 
 fn main() {
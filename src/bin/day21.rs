@@ -1,6 +1,7 @@
 use std::collections::HashMap;
+use std::fmt::Display;
 
-use advent_2022::{read_aoc_lines, InputIterator, OptionUtils};
+use advent_2022::{check, read_aoc_lines, InputError, InputIterator, OptionUtils};
 use anyhow::Result;
 
 enum Operator {
@@ -10,6 +11,17 @@ enum Operator {
     Div,
 }
 
+impl Operator {
+    fn symbol(&self) -> char {
+        match self {
+            Operator::Add => '+',
+            Operator::Sub => '-',
+            Operator::Mult => '*',
+            Operator::Div => '/',
+        }
+    }
+}
+
 enum Monkey {
     Int(i64),
     Operation {
@@ -20,58 +32,166 @@ enum Monkey {
     Unknown,
 }
 
-fn evaluate(
+enum OrderError<'a> {
+    Undefined { monkey: &'a str, missing: &'a str },
+    Cycle(&'a str),
+}
+
+impl OrderError<'_> {
+    fn message(&self) -> String {
+        match self {
+            OrderError::Undefined { monkey, missing } => {
+                format!("monkey {} refers to undefined monkey {}", monkey, missing)
+            }
+            OrderError::Cycle(monkey) => format!("monkey {} depends on itself", monkey),
+        }
+    }
+}
+
+// Every monkey reachable from `roots`, each after the monkeys it depends on.
+fn topological_order<'a, R: IntoIterator<Item = &'a str>>(
+    monkeys: &'a HashMap<String, Monkey>,
+    roots: R,
+) -> Result<Vec<&'a str>, OrderError<'a>> {
+    // false while a monkey's dependencies are being visited, true once it's in the order.
+    let mut done: HashMap<&str, bool> = HashMap::new();
+    let mut order = vec![];
+    for root in roots {
+        let mut stack = vec![(root, false)];
+        while let Some((name, dependencies_done)) = stack.pop() {
+            if dependencies_done {
+                done.insert(name, true);
+                order.push(name);
+                continue;
+            }
+            if done.contains_key(name) {
+                continue;
+            }
+            done.insert(name, false);
+            stack.push((name, true));
+            if let Some(Monkey::Operation { lhs, rhs, op: _ }) = monkeys.get(name) {
+                for dependency in [lhs.as_str(), rhs.as_str()] {
+                    match done.get(dependency) {
+                        Some(false) => return Err(OrderError::Cycle(dependency)),
+                        Some(true) => {}
+                        None if !monkeys.contains_key(dependency) => {
+                            return Err(OrderError::Undefined {
+                                monkey: name,
+                                missing: dependency,
+                            })
+                        }
+                        None => stack.push((dependency, false)),
+                    }
+                }
+            }
+        }
+    }
+    Ok(order)
+}
+
+// Computes a value for `name` bottom-up in dependency order, so deep trees don't recurse. `f`
+// gets the values of an operation's operands.
+fn fold_monkeys<T, F: FnMut(&str, &Monkey, Option<(&T, &T)>) -> Result<T>>(
     monkeys: &HashMap<String, Monkey>,
-    cache: &mut HashMap<String, i64>,
     name: &str,
-) -> Option<i64> {
-    if let Some(s) = cache.get(name) {
-        return Some(*s);
-    }
-
-    let monkey = monkeys.get(name).unwrap();
-    if let Monkey::Unknown = monkey {
-        return None;
-    }
-
-    let result = match monkeys.get(name).unwrap() {
-        Monkey::Int(x) => *x,
-        Monkey::Operation { lhs, rhs, op } => {
-            let l = evaluate(monkeys, cache, lhs);
-            let r = evaluate(monkeys, cache, rhs);
-            match op {
-                Operator::Add => l? + r?,
-                Operator::Sub => l? - r?,
-                Operator::Mult => l? * r?,
-                Operator::Div => l? / r?,
+    mut f: F,
+) -> Result<T> {
+    check(monkeys.contains_key(name), || {
+        InputError::new(format!("No monkey named {}", name))
+    })?;
+    let order = topological_order(monkeys, [name]).map_err(|e| InputError::new(e.message()))?;
+    let mut values: HashMap<&str, T> = HashMap::new();
+    for n in order {
+        let monkey = &monkeys[n];
+        let value = match monkey {
+            Monkey::Operation { lhs, rhs, op: _ } => f(
+                n,
+                monkey,
+                Some((&values[lhs.as_str()], &values[rhs.as_str()])),
+            )?,
+            _ => f(n, monkey, None)?,
+        };
+        values.insert(n, value);
+    }
+    Ok(values.remove(name).unwrap())
+}
+
+trait Number: Copy + Display + PartialEq + From<i64> {
+    fn apply(self, op: &Operator, rhs: Self) -> Result<Self, &'static str>;
+}
+
+macro_rules! impl_number {
+    ($($t:ty),*) => {$(
+        impl Number for $t {
+            fn apply(self, op: &Operator, rhs: $t) -> Result<$t, &'static str> {
+                match op {
+                    Operator::Add => self.checked_add(rhs),
+                    Operator::Sub => self.checked_sub(rhs),
+                    Operator::Mult => self.checked_mul(rhs),
+                    Operator::Div if rhs == 0 => return Err("division by zero"),
+                    Operator::Div => self.checked_div(rhs),
+                }
+                .ok_or("overflow")
             }
         }
-        _ => unreachable!(),
-    };
-    cache.insert(name.to_string(), result);
-    Some(result)
+    )*};
+}
+
+impl_number!(i64, i128);
+
+// Applies `op` in N, naming the monkey if that fails.
+fn apply_op<N: Number>(name: &str, l: N, op: &Operator, r: N) -> Result<N> {
+    Ok(l.apply(op, r).map_err(|e| {
+        InputError::new(format!(
+            "monkey {}: {} computing {} {} {}",
+            name,
+            e,
+            l,
+            op.symbol(),
+            r
+        ))
+    })?)
 }
 
-fn parse_line(s: &str) -> Result<(String, Monkey)> {
-    let (name_slice, rest) = s.split_once(": ").ok_or_err()?;
+// The value a monkey yells, or None if it depends on humn. Intermediate results must fit in N.
+fn evaluate<N: Number>(monkeys: &HashMap<String, Monkey>, name: &str) -> Result<Option<N>> {
+    fold_monkeys(
+        monkeys,
+        name,
+        |name, monkey, operands: Option<(&Option<N>, &Option<N>)>| {
+            Ok(match (monkey, operands) {
+                (Monkey::Int(x), _) => Some(N::from(*x)),
+                (Monkey::Operation { op, .. }, Some((Some(l), Some(r)))) => {
+                    Some(apply_op(name, *l, op, *r)?)
+                }
+                _ => None,
+            })
+        },
+    )
+}
+
+fn parse_line(s: &str, line_number: usize) -> Result<(String, Monkey)> {
+    let error = |msg: &str| InputError::new(format!("line {}: {}", line_number, msg));
+    let (name_slice, rest) = s
+        .split_once(": ")
+        .ok_or_else(|| error("expected `name: job`"))?;
     let name = name_slice.to_string();
     if let Ok(i) = rest.parse::<i64>() {
         return Ok((name, Monkey::Int(i)));
     }
 
     let mut split = rest.split(' ');
-    let (left, operator, right) = (
-        split.next().ok_or_err()?,
-        split.next().ok_or_err()?,
-        split.next().ok_or_err()?,
-    );
+    let (left, operator, right) = match (split.next(), split.next(), split.next(), split.next()) {
+        (Some(l), Some(o), Some(r), None) => (l, o, r),
+        _ => return Err(error("expected a number or `lhs op rhs`").into()),
+    };
 
     let op = match operator {
         "+" => Operator::Add,
         "-" => Operator::Sub,
         "*" => Operator::Mult,
         "/" => Operator::Div,
-        _ => unreachable!(""),
+        _ => return Err(error(&format!("unknown operator {}", operator)).into()),
     };
 
     Ok((
@@ -84,80 +204,312 @@ fn parse_line(s: &str) -> Result<(String, Monkey)> {
     ))
 }
 
+// Parses every monkey and checks that all referenced monkeys exist and none depends on itself.
+fn parse_monkeys<I: InputIterator>(input: I) -> Result<HashMap<String, Monkey>> {
+    let mut monkeys = HashMap::new();
+    let mut lines = HashMap::new();
+    for (i, l) in input.enumerate() {
+        let (name, monkey) = parse_line(l.as_ref(), i + 1)?;
+        if let Some(first) = lines.insert(name.clone(), i + 1) {
+            return Err(InputError::new(format!(
+                "line {}: monkey {} was already defined on line {}",
+                i + 1,
+                name,
+                first
+            ))
+            .into());
+        }
+        monkeys.insert(name, monkey);
+    }
+
+    let mut names: Vec<_> = monkeys.keys().map(|x| x.as_str()).collect();
+    names.sort_by_key(|x| lines[*x]);
+    if let Err(e) = topological_order(&monkeys, names) {
+        let name = match e {
+            OrderError::Undefined { monkey, .. } => monkey,
+            OrderError::Cycle(monkey) => monkey,
+        };
+        return Err(InputError::new(format!("line {}: {}", lines[name], e.message())).into());
+    }
+    Ok(monkeys)
+}
+
+fn do_part1<N: Number, I: InputIterator>(input: I) -> Result<N> {
+    let monkeys = parse_monkeys(input)?;
+    Ok(evaluate(&monkeys, "root")?.ok_or_err()?)
+}
+
 fn part1<I: InputIterator>(input: I) -> Result<i64> {
-    let monkeys = input
-        .map(|x| parse_line(x.as_ref()))
-        .collect::<Result<HashMap<_, _>>>()?;
+    do_part1(input)
+}
 
-    let mut cache = HashMap::new();
-    Ok(evaluate(&monkeys, &mut cache, "root").ok_or_err()?)
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
 }
 
-fn find_human_value(
-    monkeys: &HashMap<String, Monkey>,
-    cache: &HashMap<String, i64>,
-    name: &str,
-    needed_value: i64,
-) -> i64 {
-    if name == "humn" {
-        return needed_value;
+// An exact fraction, kept in lowest terms with a positive denominator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rational {
+    num: i128,
+    den: i128,
+}
+
+impl Rational {
+    fn new(num: i128, den: i128) -> Rational {
+        let g = gcd(num, den).max(1) * den.signum();
+        Rational {
+            num: num / g,
+            den: den / g,
+        }
     }
 
-    let (lhs, rhs, op) = if let Monkey::Operation { lhs, rhs, op } = monkeys.get(name).unwrap() {
-        (lhs, rhs, op)
-    } else {
-        unreachable!("");
-    };
+    fn from_int(x: i64) -> Rational {
+        Rational::new(x as i128, 1)
+    }
 
-    match (cache.get(lhs), cache.get(rhs)) {
-        (Some(l), None) => match op {
-            Operator::Add => find_human_value(monkeys, cache, rhs, needed_value - *l),
-            Operator::Sub => find_human_value(monkeys, cache, rhs, *l - needed_value),
-            Operator::Mult => find_human_value(monkeys, cache, rhs, needed_value / *l),
-            Operator::Div => find_human_value(monkeys, cache, rhs, *l / needed_value),
-        },
-        (None, Some(r)) => match op {
-            Operator::Add => find_human_value(monkeys, cache, lhs, needed_value - *r),
-            Operator::Sub => find_human_value(monkeys, cache, lhs, needed_value + *r),
-            Operator::Mult => find_human_value(monkeys, cache, lhs, needed_value / *r),
-            Operator::Div => find_human_value(monkeys, cache, lhs, needed_value * *r),
+    fn from_parts(num: Option<i128>, den: Option<i128>) -> Option<Rational> {
+        Some(Rational::new(num?, den?))
+    }
+
+    fn is_zero(&self) -> bool {
+        self.num == 0
+    }
+
+    fn checked_add(self, rhs: Rational) -> Option<Rational> {
+        let l = self.num.checked_mul(rhs.den)?;
+        let r = rhs.num.checked_mul(self.den)?;
+        Rational::from_parts(l.checked_add(r), self.den.checked_mul(rhs.den))
+    }
+
+    fn checked_sub(self, rhs: Rational) -> Option<Rational> {
+        self.checked_add(Rational {
+            num: rhs.num.checked_neg()?,
+            den: rhs.den,
+        })
+    }
+
+    fn checked_mul(self, rhs: Rational) -> Option<Rational> {
+        Rational::from_parts(self.num.checked_mul(rhs.num), self.den.checked_mul(rhs.den))
+    }
+
+    // None on overflow or when dividing by zero.
+    fn checked_div(self, rhs: Rational) -> Option<Rational> {
+        if rhs.is_zero() {
+            return None;
+        }
+        Rational::from_parts(self.num.checked_mul(rhs.den), self.den.checked_mul(rhs.num))
+    }
+}
+
+// `a * humn + b`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Linear {
+    a: Rational,
+    b: Rational,
+}
+
+impl Linear {
+    fn constant(b: Rational) -> Linear {
+        Linear {
+            a: Rational::from_int(0),
+            b,
+        }
+    }
+
+    fn scale(self, k: Rational) -> Option<Linear> {
+        Some(Linear {
+            a: self.a.checked_mul(k)?,
+            b: self.b.checked_mul(k)?,
+        })
+    }
+
+    fn apply(self, op: &Operator, rhs: Linear) -> Result<Linear, &'static str> {
+        let result = match op {
+            Operator::Add => self.a.checked_add(rhs.a).zip(self.b.checked_add(rhs.b)),
+            Operator::Sub => self.a.checked_sub(rhs.a).zip(self.b.checked_sub(rhs.b)),
+            Operator::Mult if self.a.is_zero() => return rhs.scale(self.b).ok_or("overflow"),
+            Operator::Mult if rhs.a.is_zero() => return self.scale(rhs.b).ok_or("overflow"),
+            Operator::Mult => return Err("humn is multiplied by itself"),
+            Operator::Div if !rhs.a.is_zero() => return Err("division by humn"),
+            Operator::Div if rhs.b.is_zero() => return Err("division by zero"),
+            Operator::Div => {
+                let k = Rational::new(1, 1).checked_div(rhs.b);
+                return k.and_then(|k| self.scale(k)).ok_or("overflow");
+            }
+        };
+        result.map(|(a, b)| Linear { a, b }).ok_or("overflow")
+    }
+}
+
+// Reduces a monkey to a linear function of humn (which must be `Monkey::Unknown`).
+fn linearize(monkeys: &HashMap<String, Monkey>, name: &str) -> Result<Linear> {
+    fold_monkeys(
+        monkeys,
+        name,
+        |name, monkey, operands: Option<(&Linear, &Linear)>| {
+            Ok(match (monkey, operands) {
+                (Monkey::Int(x), _) => Linear::constant(Rational::from_int(*x)),
+                (Monkey::Unknown, _) => Linear {
+                    a: Rational::from_int(1),
+                    b: Rational::from_int(0),
+                },
+                (Monkey::Operation { op, .. }, Some((l, r))) => l
+                    .apply(op, *r)
+                    .map_err(|e| InputError::new(format!("monkey {}: {}", name, e)))?,
+                (Monkey::Operation { .. }, None) => unreachable!(),
+            })
         },
-        _ => unreachable!(""),
+    )
+}
+
+// Solves root's `lhs == rhs` for humn, which may appear on either side or on both.
+fn solve_for_human(monkeys: &HashMap<String, Monkey>) -> Result<i64> {
+    let (lhs, rhs) = match monkeys.get("root").ok_or_err()? {
+        Monkey::Operation { lhs, rhs, op: _ } => (lhs, rhs),
+        _ => return Err(InputError::new("root is not an operation").into()),
+    };
+    let l = linearize(monkeys, lhs)?;
+    let r = linearize(monkeys, rhs)?;
+    let overflow = || InputError::new("overflow solving for humn");
+    let a = l.a.checked_sub(r.a).ok_or_else(overflow)?;
+    let b = r.b.checked_sub(l.b).ok_or_else(overflow)?;
+    if a.is_zero() {
+        return Err(if b.is_zero() {
+            InputError::new("Any value of humn satisfies root")
+        } else {
+            InputError::new("No value of humn satisfies root")
+        }
+        .into());
     }
+    let x = b.checked_div(a).ok_or_else(overflow)?;
+    check(x.den == 1, || {
+        InputError::new(format!("humn would have to be {}/{}", x.num, x.den))
+    })?;
+    Ok(i64::try_from(x.num)?)
 }
 
-fn part2<I: InputIterator>(input: I) -> Result<i64> {
-    let mut monkeys = input
-        .map(|x| parse_line(x.as_ref()))
-        .collect::<Result<HashMap<_, _>>>()?;
+impl Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+// Writes out the formula a monkey computes, fully parenthesised. With `fold`, subtrees that
+// don't involve humn are replaced by their value, computed with part 1's integer division.
+fn expand(monkeys: &HashMap<String, Monkey>, name: &str, fold: bool) -> Result<String> {
+    let mut constants: HashMap<&str, i64> = HashMap::new();
+    if fold {
+        fold_monkeys(
+            monkeys,
+            name,
+            |n, monkey, operands: Option<(&Option<i64>, &Option<i64>)>| {
+                let value = match (monkey, operands) {
+                    (Monkey::Int(x), _) => Some(*x),
+                    (Monkey::Operation { op, .. }, Some((Some(l), Some(r)))) => {
+                        Some(apply_op(n, *l, op, *r)?)
+                    }
+                    _ => None,
+                };
+                if let Some(v) = value {
+                    constants.insert(monkeys.get_key_value(n).unwrap().0, v);
+                }
+                Ok(value)
+            },
+        )?;
+    }
+
+    // Writes the formula left to right with an explicit stack, so long chains of monkeys
+    // neither recurse deeply nor copy their subformulas at every level.
+    enum Item<'a> {
+        Monkey(&'a str),
+        Operator(char),
+        Text(&'static str),
+    }
+    let mut formula = String::new();
+    let mut stack = vec![Item::Monkey(name)];
+    while let Some(item) = stack.pop() {
+        let n = match item {
+            Item::Text(t) => {
+                formula.push_str(t);
+                continue;
+            }
+            Item::Operator(c) => {
+                formula.push_str(&format!(" {} ", c));
+                continue;
+            }
+            Item::Monkey(n) => n,
+        };
+        if let Some(v) = constants.get(n) {
+            formula.push_str(&v.to_string());
+            continue;
+        }
+        match monkeys.get(n).ok_or_err()? {
+            Monkey::Int(x) => formula.push_str(&x.to_string()),
+            Monkey::Unknown => formula.push_str("humn"),
+            Monkey::Operation { lhs, rhs, op } => {
+                formula.push('(');
+                stack.push(Item::Text(")"));
+                stack.push(Item::Monkey(rhs));
+                stack.push(Item::Operator(op.symbol()));
+                stack.push(Item::Monkey(lhs));
+            }
+        }
+    }
+    Ok(formula)
+}
+
+// The equation root checks in part 2, with constant subtrees folded.
+fn human_equation(monkeys: &HashMap<String, Monkey>) -> Result<String> {
+    match monkeys.get("root").ok_or_err()? {
+        Monkey::Operation { lhs, rhs, op: _ } => Ok(format!(
+            "{} = {}",
+            expand(monkeys, lhs, true)?,
+            expand(monkeys, rhs, true)?
+        )),
+        _ => Err(InputError::new("root is not an operation").into()),
+    }
+}
+
+// The monkeys with humn replaced by the unknown value part 2 solves for.
+fn parse_with_human<I: InputIterator>(input: I) -> Result<HashMap<String, Monkey>> {
+    let mut monkeys = parse_monkeys(input)?;
 
     monkeys
         .insert("humn".to_string(), Monkey::Unknown)
         .ok_or_err()?;
-    let mut cache = HashMap::new();
-    assert!(evaluate(&monkeys, &mut cache, "root").is_none());
-    let root = monkeys.remove("root").unwrap();
+    Ok(monkeys)
+}
 
-    let (lhs, rhs) = if let Monkey::Operation { lhs, rhs, op: _ } = root {
-        (lhs, rhs)
-    } else {
-        unreachable!("")
-    };
+fn part2<I: InputIterator>(input: I) -> Result<i64> {
+    solve_for_human(&parse_with_human(input)?)
+}
 
-    Ok(match (cache.get(&lhs), cache.get(&rhs)) {
-        (Some(l), None) => find_human_value(&monkeys, &cache, &rhs, *l),
-        (None, Some(r)) => find_human_value(&monkeys, &cache, &lhs, *r),
-        _ => unreachable!(""),
-    })
+fn part2_equation<I: InputIterator>(input: I) -> Result<String> {
+    human_equation(&parse_with_human(input)?)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::do_part1;
+    use crate::expand;
+    use crate::human_equation;
+    use crate::parse_line;
+    use crate::parse_monkeys;
     use crate::part1;
     use crate::part2;
-    #[test]
-    fn dayn_test() {
-        let input = vec![
+    use crate::part2_equation;
+    use crate::Monkey;
+
+    fn example() -> Vec<&'static str> {
+        vec![
             "root: pppw + sjmn",
             "dbpl: 5",
             "cczh: sllz + lgvd",
@@ -173,13 +525,200 @@ mod tests {
             "lgvd: ljgn * ptdq",
             "drzm: hmdt - zczc",
             "hmdt: 32",
-        ];
+        ]
+    }
+
+    #[test]
+    fn dayn_test() {
+        let input = example();
         assert_eq!(part1(input.iter()).unwrap(), 152);
         assert_eq!(part2(input.iter()).unwrap(), 301);
+        assert_eq!(
+            part2_equation(input.iter()).unwrap(),
+            "((4 + (2 * (humn - 3))) / 4) = 150"
+        );
+    }
+
+    #[test]
+    fn expand_test() {
+        let mut monkeys = parse_monkeys(example().iter()).unwrap();
+        assert_eq!(
+            expand(&monkeys, "pppw", false).unwrap(),
+            "((4 + (2 * (5 - 3))) / 4)"
+        );
+        assert_eq!(expand(&monkeys, "root", true).unwrap(), "152");
+
+        monkeys.insert("humn".to_string(), Monkey::Unknown);
+        assert_eq!(
+            expand(&monkeys, "root", false).unwrap(),
+            "(((4 + (2 * (humn - 3))) / 4) + ((32 - 2) * 5))"
+        );
+        assert_eq!(
+            human_equation(&monkeys).unwrap(),
+            "((4 + (2 * (humn - 3))) / 4) = 150"
+        );
+
+        monkeys.insert("hmdt".to_string(), parse_line("hmdt: 7 / 2", 1).unwrap().1);
+        monkeys.insert("7".to_string(), Monkey::Int(7));
+        monkeys.insert("2".to_string(), Monkey::Int(2));
+        assert_eq!(
+            human_equation(&monkeys).unwrap(),
+            "((4 + (2 * (humn - 3))) / 4) = 5"
+        );
+
+        // Folding rounds divisions down, as part 1 does.
+        let inexact = ["root: a + b", "a: x / y", "x: 7", "y: 2", "b: 1"];
+        let mut monkeys = parse_monkeys(inexact.iter()).unwrap();
+        assert_eq!(part1(inexact.iter()).unwrap(), 4);
+        assert_eq!(expand(&monkeys, "root", true).unwrap(), "4");
+        assert_eq!(expand(&monkeys, "root", false).unwrap(), "((7 / 2) + 1)");
+        monkeys.insert("b".to_string(), parse_line("b: humn * y", 5).unwrap().1);
+        monkeys.insert("humn".to_string(), Monkey::Unknown);
+        assert_eq!(expand(&monkeys, "root", true).unwrap(), "(3 + (humn * 2))");
+    }
+
+    #[test]
+    fn validation_test() {
+        let error = |input: &[&str]| parse_monkeys(input.iter()).err().unwrap().to_string();
+        assert_eq!(
+            error(&["root: a + b", "a: 1", "b: a % 2"]),
+            "Input error: line 3: unknown operator %"
+        );
+        assert_eq!(
+            error(&["root: a + b", "a: 1"]),
+            "Input error: line 1: monkey root refers to undefined monkey b"
+        );
+        assert_eq!(
+            error(&["root: a + b", "a: 1", "b: c * a", "c: b - a"]),
+            "Input error: line 3: monkey b depends on itself"
+        );
+        assert_eq!(
+            error(&["root: a + a", "a: 1", "a: 2"]),
+            "Input error: line 3: monkey a was already defined on line 2"
+        );
+        assert_eq!(
+            error(&["root: a +", "a: 1"]),
+            "Input error: line 1: expected a number or `lhs op rhs`"
+        );
+        assert!(parse_monkeys(["x: x + x"].iter()).is_err());
+
+        // Long chains are evaluated without recursing once per monkey.
+        let mut chain: Vec<_> = (0..30000)
+            .map(|i| format!("m{}: m{} + one", i, i + 1))
+            .collect();
+        chain.push("m30000: 0".to_string());
+        chain.push("one: 1".to_string());
+        chain.push("root: m0 + one".to_string());
+        assert_eq!(part1(chain.iter()).unwrap(), 30001);
+        let mut monkeys = parse_monkeys(chain.iter()).unwrap();
+        assert_eq!(expand(&monkeys, "root", true).unwrap(), "30001");
+        let formula = expand(&monkeys, "root", false).unwrap();
+        assert_eq!(formula, "(".repeat(30001) + "0" + &" + 1)".repeat(30001));
+        monkeys.insert("humn".to_string(), Monkey::Unknown);
+        monkeys.insert(
+            "m30000".to_string(),
+            parse_line("m30000: humn * one", 1).unwrap().1,
+        );
+        assert_eq!(
+            expand(&monkeys, "root", true).unwrap(),
+            "(".repeat(30002) + "humn * 1)" + &" + 1)".repeat(30001)
+        );
+    }
+
+    #[test]
+    fn checked_test() {
+        let error = |input: &[&str]| part1(input.iter()).err().unwrap().to_string();
+        assert_eq!(
+            error(&["root: a + b", "a: 1", "b: a / zero", "zero: 0"]),
+            "Input error: monkey b: division by zero computing 1 / 0"
+        );
+        let big = ["root: a * a", "a: 9000000000000"];
+        assert_eq!(
+            error(&big),
+            "Input error: monkey root: overflow computing 9000000000000 * 9000000000000"
+        );
+        assert_eq!(
+            do_part1::<i128, _>(big.iter()).unwrap(),
+            81000000000000000000000000
+        );
+
+        let zero_divisor = [
+            "root: a + b",
+            "a: humn / zero",
+            "zero: 0",
+            "b: 1",
+            "humn: 0",
+        ];
+        assert_eq!(
+            part2(zero_divisor.iter()).err().unwrap().to_string(),
+            "Input error: monkey a: division by zero"
+        );
+    }
+
+    #[test]
+    fn symbolic_test() {
+        // humn on both sides: 3 * humn - 4 == humn + 10, so humn is 7.
+        let both = [
+            "root: left + right",
+            "left: triple - four",
+            "triple: three * humn",
+            "three: 3",
+            "four: 4",
+            "right: humn + ten",
+            "ten: 10",
+            "humn: 0",
+        ];
+        assert_eq!(part2(both.iter()).unwrap(), 7);
+
+        // Intermediate results aren't integers: humn / 4 * 6 == 9, so humn is 6.
+        let fractional = [
+            "root: left + nine",
+            "left: quarter * six",
+            "quarter: humn / four",
+            "four: 4",
+            "six: 6",
+            "nine: 9",
+            "humn: 0",
+        ];
+        assert_eq!(part2(fractional.iter()).unwrap(), 6);
+
+        let no_solution = [
+            "root: humn + right",
+            "right: humn + one",
+            "one: 1",
+            "humn: 0",
+        ];
+        assert!(part2(no_solution.iter()).is_err());
+        let any_solution = [
+            "root: humn + right",
+            "right: humn * one",
+            "one: 1",
+            "humn: 0",
+        ];
+        assert!(part2(any_solution.iter()).is_err());
+        let non_integer = [
+            "root: double + three",
+            "double: humn * two",
+            "two: 2",
+            "three: 3",
+            "humn: 0",
+        ];
+        assert!(part2(non_integer.iter()).is_err());
+        let quadratic = [
+            "root: square + four",
+            "square: humn * humn",
+            "four: 4",
+            "humn: 0",
+        ];
+        assert!(part2(quadratic.iter()).is_err());
     }
 }
 
 fn main() {
     print!("{:?}\n", part1(read_aoc_lines!()));
     print!("{:?}\n", part2(read_aoc_lines!()));
+    // Pass --equation to also show the equation part 2 solves.
+    if std::env::args().any(|a| a == "--equation") {
+        print!("{:?}\n", part2_equation(read_aoc_lines!()));
+    }
 }
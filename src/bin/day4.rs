@@ -1,4 +1,4 @@
-use advent_2022::{check, read_aoc_lines, InputError, InputIterator, OptionUtils};
+use advent_2022::{check, read_aoc_lines, InputError, InputIterator, IntervalSet, OptionUtils};
 use anyhow::Result;
 
 struct Assignment {
@@ -14,13 +14,16 @@ impl Assignment {
         Ok(Assignment { start, end })
     }
 
+    fn sections(&self) -> IntervalSet {
+        [(self.start, self.end)].into_iter().collect()
+    }
+
     fn subset_of(&self, other: &Assignment) -> bool {
-        self.start >= other.start && self.end <= other.end
+        self.sections().difference(&other.sections()).is_empty()
     }
 
     fn intersects_with(&self, other: &Assignment) -> bool {
-        let r = self.start..=self.end;
-        r.contains(&other.start) || r.contains(&other.end) || self.subset_of(other)
+        !self.sections().intersection(&other.sections()).is_empty()
     }
 }
 
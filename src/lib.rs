@@ -3,9 +3,13 @@ use std::error::Error;
 use std::fmt::Display;
 use std::fs::File;
 use std::io::{self, BufRead};
-use std::ops::{Index, IndexMut, Add, Sub};
+use std::ops::{Index, IndexMut, Add, Sub, Neg, Mul, Div};
 use std::path::{Path, PathBuf};
 use std::fmt::Debug;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 use thiserror::Error;
 
 pub fn read_lines<P: AsRef<Path>>(filename: P) -> impl Iterator<Item = String> {
@@ -236,7 +240,166 @@ impl From<(i64, i64)> for Coord {
     }
 }
 
-#[derive(Debug)]
+impl Coord {
+    pub fn manhattan(self, other: Coord) -> i64 {
+        (self.i - other.i).abs() + (self.j - other.j).abs()
+    }
+
+    pub fn chebyshev(self, other: Coord) -> i64 {
+        (self.i - other.i).abs().max((self.j - other.j).abs())
+    }
+}
+
+// All the coordinates within a Manhattan distance of `radius` from `center`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Diamond {
+    pub center: Coord,
+    pub radius: i64,
+}
+
+impl Diamond {
+    pub fn new(center: Coord, radius: i64) -> Diamond {
+        Diamond {center, radius}
+    }
+
+    // The smallest diamond around `center` that reaches `edge`.
+    pub fn through(center: Coord, edge: Coord) -> Diamond {
+        Diamond::new(center, center.manhattan(edge))
+    }
+
+    pub fn contains<C: Into<Coord>>(&self, c: C) -> bool {
+        self.center.manhattan(c.into()) <= self.radius
+    }
+
+    // The range of columns [start, end] the diamond covers on row `i`, if any.
+    pub fn row(&self, i: i64) -> Option<(i64, i64)> {
+        let remaining = self.radius - (self.center.i - i).abs();
+        (remaining >= 0).then(|| (self.center.j - remaining, self.center.j + remaining))
+    }
+
+    // The coordinates at exactly `radius` from the center, clockwise from the top corner.
+    pub fn boundary(&self) -> impl Iterator<Item = Coord> {
+        let (center, r) = (self.center, self.radius);
+        let count = if r == 0 { 1 } else { 4 * r.max(0) };
+        (0..count).map(move |k| {
+            if r == 0 {
+                return center;
+            }
+            let t = k % r;
+            let offset = match k / r {
+                0 => Coord {i: t - r, j: t},
+                1 => Coord {i: t, j: r - t},
+                2 => Coord {i: r - t, j: -t},
+                _ => Coord {i: -t, j: t - r},
+            };
+            center + offset
+        })
+    }
+}
+
+// A point in N dimensions.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct MultiCoord<const N: usize> {
+    pub coords: [i64; N],
+}
+
+impl<const N: usize> Default for MultiCoord<N> {
+    fn default() -> Self {
+        MultiCoord {coords: [0; N]}
+    }
+}
+
+impl<const N: usize> From<[i64; N]> for MultiCoord<N> {
+    fn from(coords: [i64; N]) -> Self {
+        MultiCoord {coords}
+    }
+}
+
+impl From<Coord> for MultiCoord<2> {
+    fn from(c: Coord) -> Self {
+        MultiCoord {coords: [c.i, c.j]}
+    }
+}
+
+impl From<MultiCoord<2>> for Coord {
+    fn from(c: MultiCoord<2>) -> Self {
+        Coord {i: c.coords[0], j: c.coords[1]}
+    }
+}
+
+impl<const N: usize> Add<MultiCoord<N>> for MultiCoord<N> {
+    type Output = MultiCoord<N>;
+
+    fn add(self, rhs: MultiCoord<N>) -> Self::Output {
+        MultiCoord {coords: std::array::from_fn(|k| self.coords[k] + rhs.coords[k])}
+    }
+}
+
+impl<const N: usize> Sub<MultiCoord<N>> for MultiCoord<N> {
+    type Output = MultiCoord<N>;
+
+    fn sub(self, rhs: MultiCoord<N>) -> Self::Output {
+        MultiCoord {coords: std::array::from_fn(|k| self.coords[k] - rhs.coords[k])}
+    }
+}
+
+impl<const N: usize> Neg for MultiCoord<N> {
+    type Output = MultiCoord<N>;
+
+    fn neg(self) -> Self::Output {
+        MultiCoord {coords: self.coords.map(|x| -x)}
+    }
+}
+
+impl<const N: usize> Mul<i64> for MultiCoord<N> {
+    type Output = MultiCoord<N>;
+
+    fn mul(self, rhs: i64) -> Self::Output {
+        MultiCoord {coords: self.coords.map(|x| x * rhs)}
+    }
+}
+
+impl<const N: usize> Div<i64> for MultiCoord<N> {
+    type Output = MultiCoord<N>;
+
+    fn div(self, rhs: i64) -> Self::Output {
+        MultiCoord {coords: self.coords.map(|x| x / rhs)}
+    }
+}
+
+impl<const N: usize> MultiCoord<N> {
+    // The vector of length 1 along `axis`.
+    pub fn unit(axis: usize) -> MultiCoord<N> {
+        let mut r = MultiCoord::default();
+        r.coords[axis] = 1;
+        r
+    }
+
+    // The 2N points that share a face with this one, in +/- order for each axis.
+    pub fn neighbors(self) -> impl Iterator<Item = MultiCoord<N>> {
+        (0..N).flat_map(move |axis| {
+            let unit = MultiCoord::unit(axis);
+            [self + unit, self - unit]
+        })
+    }
+
+    pub fn manhattan(self, other: MultiCoord<N>) -> i64 {
+        (0..N).map(|k| (self.coords[k] - other.coords[k]).abs()).sum()
+    }
+
+    // The smallest and largest coordinate along every axis, or None if there are no points.
+    pub fn bounding_box<I: IntoIterator<Item = MultiCoord<N>>>(points: I) -> Option<(MultiCoord<N>, MultiCoord<N>)> {
+        points.into_iter().fold(None, |acc, p| {
+            let (min, max) = acc.unwrap_or((p, p));
+            Some((
+                MultiCoord {coords: std::array::from_fn(|k| min.coords[k].min(p.coords[k]))},
+                MultiCoord {coords: std::array::from_fn(|k| max.coords[k].max(p.coords[k]))},
+            ))
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Grid<T> {
     height: i64,
     width: i64,
@@ -321,6 +484,143 @@ impl<T, C: Into<Coord>> IndexMut<C> for Grid<T> {
 
 }
 
+// A dense 3-D grid covering the box from `min` to `max` (inclusive).
+#[derive(Debug, Clone)]
+pub struct Grid3<T> {
+    min: MultiCoord<3>,
+    max: MultiCoord<3>,
+    data: Vec<T>,
+}
+
+impl<T: Clone> Grid3<T> {
+    pub fn new(min: MultiCoord<3>, max: MultiCoord<3>, val: T) -> Grid3<T> {
+        let volume: i64 = (0..3).map(|k| (max.coords[k] - min.coords[k] + 1).max(0)).product();
+        Grid3 {min, max, data: vec![val; volume as usize]}
+    }
+}
+
+impl<T> Grid3<T> {
+    pub fn min(&self) -> MultiCoord<3> { self.min }
+    pub fn max(&self) -> MultiCoord<3> { self.max }
+
+    fn dims(&self) -> [i64; 3] {
+        std::array::from_fn(|k| self.max.coords[k] - self.min.coords[k] + 1)
+    }
+
+    fn con_ind(&self, p: MultiCoord<3>) -> usize {
+        let [_, h, w] = self.dims();
+        let d = (p - self.min).coords;
+        ((d[0] * h + d[1]) * w + d[2]) as usize
+    }
+
+    fn coord_at(&self, index: usize) -> MultiCoord<3> {
+        let [_, h, w] = self.dims();
+        let index = index as i64;
+        self.min + MultiCoord::from([index / (h * w), index / w % h, index % w])
+    }
+
+    pub fn contains<C: Into<MultiCoord<3>>>(&self, p: C) -> bool {
+        let p = p.into();
+        (0..3).all(|k| (self.min.coords[k]..=self.max.coords[k]).contains(&p.coords[k]))
+    }
+
+    pub fn get<C: Into<MultiCoord<3>>>(&self, p: C) -> Option<&T> {
+        let p = p.into();
+        if !self.contains(p) {
+            None
+        } else {
+            Some(&self.data[self.con_ind(p)])
+        }
+    }
+
+    pub fn get_mut<C: Into<MultiCoord<3>>>(&mut self, p: C) -> Option<&mut T> {
+        let p = p.into();
+        if !self.contains(p) {
+            None
+        } else {
+            let i = self.con_ind(p);
+            Some(&mut self.data[i])
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MultiCoord<3>, &T)> + '_ {
+        self.data.iter().enumerate().map(|(i, x)| (self.coord_at(i), x))
+    }
+
+    // The cells on the faces of the box.
+    pub fn boundary(&self) -> impl Iterator<Item = MultiCoord<3>> + '_ {
+        (0..self.data.len()).map(|i| self.coord_at(i)).filter(|p| {
+            (0..3).any(|k| p.coords[k] == self.min.coords[k] || p.coords[k] == self.max.coords[k])
+        })
+    }
+
+    // Marks every cell reachable from `starts` through faces of passable cells.
+    pub fn flood_fill<I, F>(&self, starts: I, passable: F) -> Grid3<bool>
+    where
+        I: IntoIterator<Item = MultiCoord<3>>,
+        F: Fn(&T) -> bool,
+    {
+        let mut filled = Grid3::new(self.min, self.max, false);
+        let mut stack: Vec<_> = starts.into_iter().filter(|p| self.get(*p).is_some_and(&passable)).collect();
+        for p in stack.iter() {
+            filled[*p] = true;
+        }
+        while let Some(p) = stack.pop() {
+            for n in p.neighbors() {
+                if self.get(n).is_some_and(&passable) && !filled[n] {
+                    filled[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+        filled
+    }
+
+    pub fn fill_from_boundary<F: Fn(&T) -> bool>(&self, passable: F) -> Grid3<bool> {
+        self.flood_fill(self.boundary(), passable)
+    }
+
+    // Groups the included cells into face-connected components.
+    pub fn components<F: Fn(&T) -> bool>(&self, include: F) -> Vec<Vec<MultiCoord<3>>> {
+        let mut seen = Grid3::new(self.min, self.max, false);
+        let mut components = vec![];
+        for (i, x) in self.data.iter().enumerate() {
+            let start = self.coord_at(i);
+            if !include(x) || seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut component = vec![start];
+            let mut next = 0;
+            while let Some(&p) = component.get(next) {
+                next += 1;
+                for n in p.neighbors() {
+                    if self.get(n).is_some_and(&include) && !seen[n] {
+                        seen[n] = true;
+                        component.push(n);
+                    }
+                }
+            }
+            components.push(component);
+        }
+        components
+    }
+}
+
+impl<T, C: Into<MultiCoord<3>>> Index<C> for Grid3<T> {
+    type Output = T;
+
+    fn index(&self, index: C) -> &Self::Output {
+        self.get(index).unwrap()
+    }
+}
+
+impl<T, C: Into<MultiCoord<3>>> IndexMut<C> for Grid3<T> {
+    fn index_mut(&mut self, index: C) -> &mut Self::Output {
+        self.get_mut(index).unwrap()
+    }
+}
+
 pub fn show_bool_grid(grid: &Grid<bool>) -> Vec<String> {
     let mut result = vec![];
     for i in 0..grid.height() {
@@ -335,4 +635,312 @@ pub fn show_bool_grid(grid: &Grid<bool>) -> Vec<String> {
         result.push(s);
     }
     result
-}
\ No newline at end of file
+}
+
+// A set of integers stored as sorted, disjoint, non-adjacent closed intervals [start, end].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IntervalSet {
+    intervals: Vec<(i64, i64)>,
+}
+
+impl FromIterator<(i64, i64)> for IntervalSet {
+    fn from_iter<T: IntoIterator<Item = (i64, i64)>>(iter: T) -> Self {
+        let mut sorted: Vec<_> = iter.into_iter().filter(|(s, e)| s <= e).collect();
+        sorted.sort();
+        let mut intervals: Vec<(i64, i64)> = Vec::with_capacity(sorted.len());
+        for (start, end) in sorted {
+            match intervals.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                _ => intervals.push((start, end)),
+            }
+        }
+        IntervalSet { intervals }
+    }
+}
+
+impl IntervalSet {
+    pub fn new() -> IntervalSet {
+        Default::default()
+    }
+
+    pub fn intervals(&self) -> &[(i64, i64)] { &self.intervals }
+
+    pub fn is_empty(&self) -> bool { self.intervals.is_empty() }
+
+    pub fn insert(&mut self, start: i64, end: i64) {
+        if start > end {
+            return;
+        }
+        // The intervals that overlap or touch [start, end] form a contiguous run.
+        let first = self.intervals.partition_point(|x| x.1.saturating_add(1) < start);
+        let last = self.intervals.partition_point(|x| x.0 <= end.saturating_add(1));
+        let merged = self.intervals[first..last]
+            .iter()
+            .fold((start, end), |acc, x| (acc.0.min(x.0), acc.1.max(x.1)));
+        self.intervals.splice(first..last, [merged]);
+    }
+
+    pub fn contains(&self, x: i64) -> bool {
+        let i = self.intervals.partition_point(|v| v.1 < x);
+        self.intervals.get(i).is_some_and(|v| v.0 <= x)
+    }
+
+    // The number of integers in the set.
+    pub fn total_length(&self) -> i64 {
+        self.intervals.iter().map(|(s, e)| e - s + 1).sum()
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        self.intervals.iter().chain(other.intervals.iter()).copied().collect()
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = vec![];
+        let (mut i, mut j) = (0, 0);
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let (a, b) = (self.intervals[i], other.intervals[j]);
+            let (start, end) = (a.0.max(b.0), a.1.min(b.1));
+            if start <= end {
+                intervals.push((start, end));
+            }
+            if a.1 < b.1 {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        IntervalSet { intervals }
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = vec![];
+        for &(start, end) in self.intervals.iter() {
+            // The first value not yet covered by `other`, if any.
+            let mut next = Some(start);
+            for &(s, e) in other.clip(start, end).intervals.iter() {
+                if let Some(n) = next.filter(|n| s > *n) {
+                    intervals.push((n, s - 1));
+                }
+                next = e.checked_add(1);
+            }
+            if let Some(n) = next.filter(|n| *n <= end) {
+                intervals.push((n, end));
+            }
+        }
+        IntervalSet { intervals }
+    }
+
+    pub fn clip(&self, start: i64, end: i64) -> IntervalSet {
+        self.intersection(&IntervalSet::from_iter([(start, end)]))
+    }
+
+    // The uncovered intervals between consecutive intervals of the set.
+    pub fn gaps(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.intervals.windows(2).map(|w| (w[0].1 + 1, w[1].0 - 1))
+    }
+}
+
+pub struct WorkQueue {
+    workers: usize,
+}
+
+impl Default for WorkQueue {
+    fn default() -> WorkQueue {
+        WorkQueue {
+            workers: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        }
+    }
+}
+
+impl WorkQueue {
+    pub fn new() -> WorkQueue {
+        Default::default()
+    }
+
+    pub fn with_workers(workers: usize) -> WorkQueue {
+        WorkQueue {
+            workers: workers.max(1),
+        }
+    }
+
+    pub fn workers(&self) -> usize { self.workers }
+
+    // Applies f to every item on at most `workers` threads, each pulling the next unclaimed item,
+    // and returns the results in the order of the items.
+    pub fn map<T: Sync, R: Send, F: Fn(&T) -> R + Sync>(&self, items: &[T], f: F) -> Vec<R> {
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(items.len()));
+        thread::scope(|s| {
+            for _ in 0..self.workers.min(items.len()) {
+                s.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else { break };
+                    let r = f(item);
+                    results.lock().unwrap().push((i, r));
+                });
+            }
+        });
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|x| x.0);
+        results.into_iter().map(|x| x.1).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Coord;
+    use crate::Diamond;
+    use crate::Grid3;
+    use crate::IntervalSet;
+    use crate::MultiCoord;
+    use crate::WorkQueue;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn diamond_test() {
+        let a = Coord {i: 1, j: -2};
+        let b = Coord {i: -3, j: 4};
+        assert_eq!(a.manhattan(b), 10);
+        assert_eq!(a.chebyshev(b), 6);
+        assert_eq!(b.manhattan(a), 10);
+
+        let d = Diamond::through(a, Coord {i: 3, j: -1});
+        assert_eq!(d.radius, 3);
+        assert!(d.contains((1, 1)) && d.contains((-1, -3)) && !d.contains((3, 0)));
+        assert_eq!(d.row(1), Some((-5, 1)));
+        assert_eq!(d.row(3), Some((-3, -1)));
+        assert_eq!(d.row(4), Some((-2, -2)));
+        assert_eq!(d.row(5), None);
+        assert_eq!(d.row(-3), None);
+
+        let boundary: Vec<_> = d.boundary().collect();
+        assert_eq!(boundary.len(), 12);
+        assert_eq!(boundary[0], Coord {i: -2, j: -2});
+        assert!(boundary.iter().all(|c| a.manhattan(*c) == 3));
+        let mut unique = boundary.clone();
+        unique.sort_by_key(|c| (c.i, c.j));
+        unique.dedup();
+        assert_eq!(unique.len(), 12);
+        assert_eq!(Diamond::new(a, 0).boundary().collect::<Vec<_>>(), [a]);
+        assert_eq!(Diamond::new(a, -1).boundary().count(), 0);
+        assert!(!Diamond::new(a, -1).contains(a));
+    }
+
+    #[test]
+    fn multi_coord_test() {
+        let a = MultiCoord::from([1, -2, 3]);
+        let b = MultiCoord::from([4, 0, -1]);
+        assert_eq!(a + b, MultiCoord::from([5, -2, 2]));
+        assert_eq!(a - b, MultiCoord::from([-3, -2, 4]));
+        assert_eq!(-a, MultiCoord::from([-1, 2, -3]));
+        assert_eq!(a * 3, MultiCoord::from([3, -6, 9]));
+        assert_eq!(b / 2, MultiCoord::from([2, 0, 0]));
+        assert_eq!(a.manhattan(b), 9);
+        assert_eq!(MultiCoord::<3>::unit(1), MultiCoord::from([0, 1, 0]));
+
+        let neighbors: Vec<_> = a.neighbors().collect();
+        assert_eq!(neighbors.len(), 6);
+        assert_eq!(neighbors[0], MultiCoord::from([2, -2, 3]));
+        assert_eq!(neighbors[5], MultiCoord::from([1, -2, 2]));
+        assert!(neighbors.iter().all(|n| n.manhattan(a) == 1));
+        assert_eq!(MultiCoord::from([0; 4]).neighbors().count(), 8);
+
+        let points = [a, b, MultiCoord::from([2, 5, 0])];
+        assert_eq!(
+            MultiCoord::bounding_box(points),
+            Some((MultiCoord::from([1, -2, -1]), MultiCoord::from([4, 5, 3])))
+        );
+        assert_eq!(MultiCoord::bounding_box([b]), Some((b, b)));
+        assert_eq!(MultiCoord::<3>::bounding_box([]), None);
+
+        let c = Coord {i: 7, j: -3};
+        assert_eq!(MultiCoord::from(c), MultiCoord::from([7, -3]));
+        assert_eq!(Coord::from(MultiCoord::from(c)), c);
+    }
+
+    #[test]
+    fn grid3_test() {
+        let mut grid = Grid3::new([-1, 0, 2].into(), [1, 2, 5].into(), 0);
+        assert_eq!(grid.iter().count(), 36);
+        assert!(grid.contains([0, 2, 5]) && !grid.contains([0, 3, 5]) && !grid.contains([-2, 0, 2]));
+        grid[[1, 2, 5]] = 7;
+        assert_eq!(grid.get([1, 2, 5]), Some(&7));
+        assert_eq!(grid.get([2, 2, 5]), None);
+        assert_eq!(grid.iter().filter(|x| *x.1 == 7).map(|x| x.0).collect::<Vec<_>>(), [MultiCoord::from([1, 2, 5])]);
+        assert_eq!(grid.boundary().count(), 36 - 2);
+        assert!(Grid3::new([0; 3].into(), [-1, 0, 0].into(), 0).iter().next().is_none());
+
+        // A hollow 3x3x3 shell inside a 5x5x7 box.
+        let mut walls = Grid3::new([0; 3].into(), [4, 4, 6].into(), false);
+        for (p, _) in Grid3::new([1; 3].into(), [3; 3].into(), ()).iter() {
+            walls[p] = p != MultiCoord::from([2; 3]);
+        }
+        let outside = walls.fill_from_boundary(|wall| !wall);
+        assert!(!outside[[2, 2, 2]] && outside[[0, 0, 0]] && !outside[[1, 1, 1]]);
+        assert_eq!(outside.iter().filter(|x| *x.1).count(), 5 * 5 * 7 - 27);
+
+        // Close off [2, 2, 4] and [2, 2, 5] on top of the shell as a second pocket.
+        for p in [[1, 2, 4], [3, 2, 4], [2, 1, 4], [2, 3, 4], [1, 2, 5], [3, 2, 5], [2, 1, 5], [2, 3, 5], [2, 2, 6]] {
+            walls[p] = true;
+        }
+        let from_corner = walls.flood_fill([MultiCoord::from([0; 3])], |wall| !wall);
+        assert_eq!(from_corner.iter().filter(|x| *x.1).count(), 5 * 5 * 7 - 26 - 9 - 3);
+        assert!(walls.flood_fill([MultiCoord::from([1; 3])], |wall| !wall).iter().all(|x| !x.1));
+        let pockets = walls.components(|wall| !wall);
+        assert_eq!(pockets.iter().map(|c| c.len()).collect::<Vec<_>>(), [5 * 5 * 7 - 26 - 9 - 3, 1, 2]);
+        assert_eq!(pockets[1], [MultiCoord::from([2; 3])]);
+    }
+
+    #[test]
+    fn interval_set_test() {
+        let mut set = IntervalSet::new();
+        set.insert(5, 7);
+        set.insert(1, 2);
+        set.insert(10, 12);
+        assert_eq!(set.intervals(), [(1, 2), (5, 7), (10, 12)]);
+        set.insert(3, 3);
+        assert_eq!(set.intervals(), [(1, 3), (5, 7), (10, 12)]);
+        set.insert(4, 9);
+        assert_eq!(set.intervals(), [(1, 12)]);
+        set.insert(2, 1);
+        assert_eq!(set.total_length(), 12);
+        assert!(set.contains(12) && !set.contains(13) && !set.contains(0));
+
+        let a: IntervalSet = [(0, 4), (8, 10), (3, 6)].into_iter().collect();
+        let b: IntervalSet = [(5, 9), (12, 12)].into_iter().collect();
+        assert_eq!(a.intervals(), [(0, 6), (8, 10)]);
+        assert_eq!(a.union(&b).intervals(), [(0, 10), (12, 12)]);
+        assert_eq!(a.intersection(&b).intervals(), [(5, 6), (8, 9)]);
+        assert_eq!(a.difference(&b).intervals(), [(0, 4), (10, 10)]);
+        assert_eq!(b.difference(&a).intervals(), [(7, 7), (12, 12)]);
+        assert_eq!(a.clip(2, 9).intervals(), [(2, 6), (8, 9)]);
+        assert_eq!(a.union(&b).gaps().collect::<Vec<_>>(), [(11, 11)]);
+        assert_eq!(a.gaps().collect::<Vec<_>>(), [(7, 7)]);
+        assert!(a.clip(20, 30).is_empty());
+        assert_eq!(a.difference(&a), IntervalSet::new());
+        let everything: IntervalSet = [(i64::MIN, i64::MAX)].into_iter().collect();
+        assert!(a.difference(&everything).is_empty());
+    }
+
+    #[test]
+    fn work_queue_test() {
+        let items: Vec<i64> = (0..100).collect();
+        let squares = WorkQueue::with_workers(3).map(&items, |x| x * x);
+        assert_eq!(squares, items.iter().map(|x| x * x).collect::<Vec<_>>());
+
+        let running = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+        WorkQueue::with_workers(2).map(&items, |_| {
+            let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(n, Ordering::SeqCst);
+            std::thread::yield_now();
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+        assert!(most.load(Ordering::SeqCst) <= 2);
+
+        let jobs: Vec<Box<dyn Fn() -> String + Sync>> =
+            vec![Box::new(|| "a".to_string()), Box::new(|| "b".to_string())];
+        assert_eq!(WorkQueue::new().map(&jobs, |job| job()), ["a", "b"]);
+        assert!(WorkQueue::new().map(&Vec::<i64>::new(), |x| *x).is_empty());
+    }
+}